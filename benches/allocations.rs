@@ -0,0 +1,83 @@
+//! Counts the allocations done while formatting 1M events with 5 fields,
+//! run with `cargo bench --bench allocations`
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    io,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use tracing::{Level, Metadata};
+use tracing_rewrite::{EventFormatter, Rewrite};
+use tracing_subscriber::fmt;
+
+const EVENTS: usize = 1_000_000;
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+// the visitor keeps values on the stack and takes its text buffer from a thread-local pool,
+// so once warmed up the only allocation left is the rewritten metadata, unless cached or in an arena
+const METADATA_ALLOCATIONS: usize = if cfg!(any(feature = "arena", feature = "cache_metadata")) {
+    0
+} else {
+    1
+};
+
+fn count(name: &str, check: fn(&Metadata<'static>) -> Option<Rewrite>) -> usize {
+    let subscriber = fmt::Subscriber::builder()
+        .with_writer(io::sink)
+        .event_format(EventFormatter::<8, _, _>::new(
+            fmt::format().compact(),
+            check,
+        ))
+        .finish();
+
+    tracing::subscriber::with_default(subscriber, || {
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        for i in 0..EVENTS {
+            tracing::error!(
+                index = i,
+                name = "name",
+                list = ?[1, 2, 3],
+                ready = true,
+                ratio = 0.5,
+                "message"
+            );
+        }
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+        println!(
+            "{name}: {allocations} allocations, {:.2} per event",
+            allocations as f64 / EVENTS as f64
+        );
+        allocations
+    })
+}
+
+fn main() {
+    let passthrough = count("passthrough", |_| None);
+    let rewrite = count("rewrite", |_| Some(Level::WARN.into()));
+
+    // a handful of allocations warm up thread-locals and pools, none of them per event
+    let steady = |allocations: usize, per_event: usize| allocations <= EVENTS * per_event + 100;
+    assert!(steady(passthrough, 0), "passthrough allocates per event");
+    assert!(
+        steady(rewrite, METADATA_ALLOCATIONS),
+        "rewrite allocates more than the metadata per event"
+    );
+}
@@ -0,0 +1,90 @@
+//! Measures the time spent formatting 1M `ERROR` events with 5 fields, comparing the plain
+//! formatter, the pass-through path and the rewrite path, with both the fixed size visitor of
+//! `EventFormatter` and the inline one of `DynEventFormatter`, run with `cargo bench --bench rewrite`,
+//! optionally adding `--features cache_metadata` or `--features arena` to compare metadata strategies
+
+use std::{
+    hint::black_box,
+    io,
+    time::{Duration, Instant},
+};
+
+use tracing::{Level, Metadata, Subscriber};
+use tracing_rewrite::{DynEventFormatter, EventFormatter, Rewrite};
+use tracing_subscriber::fmt;
+
+const EVENTS: u32 = 1_000_000;
+// best of a few runs, to smooth out the noise
+const RUNS: usize = 5;
+
+fn log() {
+    for i in 0..EVENTS {
+        tracing::error!(
+            index = black_box(i),
+            name = "name",
+            list = ?[1, 2, 3],
+            ready = true,
+            ratio = 0.5,
+            "message"
+        );
+    }
+}
+
+fn measure(subscriber: impl Subscriber + Send + Sync + 'static) -> Duration {
+    tracing::subscriber::with_default(subscriber, || {
+        (0..RUNS)
+            .map(|_| {
+                let start = Instant::now();
+                log();
+                start.elapsed()
+            })
+            .min()
+            .unwrap()
+    })
+}
+
+fn rewritten(check: fn(&Metadata<'static>) -> Option<Rewrite>) -> Duration {
+    measure(
+        fmt::Subscriber::builder()
+            .with_writer(io::sink)
+            .event_format(EventFormatter::<8, _, _>::new(
+                fmt::format().compact(),
+                check,
+            ))
+            .finish(),
+    )
+}
+
+fn report(name: &str, elapsed: Duration, baseline: Duration) {
+    let per_event = elapsed.as_nanos() as f64 / f64::from(EVENTS);
+    let overhead = (elapsed.as_nanos() as f64 - baseline.as_nanos() as f64) / f64::from(EVENTS);
+    println!("{name:<12} {elapsed:>10.2?} {per_event:>8.1} ns/event {overhead:>+8.1} ns/event");
+}
+
+fn main() {
+    let baseline = measure(
+        fmt::Subscriber::builder()
+            .with_writer(io::sink)
+            .event_format(fmt::format().compact())
+            .finish(),
+    );
+    let passthrough = rewritten(|_| None);
+    let rewrite = rewritten(|_| Some(Level::WARN.into()));
+    let dyn_rewrite = measure(
+        fmt::Subscriber::builder()
+            .with_writer(io::sink)
+            .event_format(DynEventFormatter::new(fmt::format().compact(), |_| {
+                Some(Level::WARN)
+            }))
+            .finish(),
+    );
+
+    println!(
+        "{:<12} {:>10} {:>17} {:>17}",
+        "", "total", "per event", "overhead"
+    );
+    report("baseline", baseline, baseline);
+    report("passthrough", passthrough, baseline);
+    report("rewrite", rewrite, baseline);
+    report("dyn rewrite", dyn_rewrite, baseline);
+}
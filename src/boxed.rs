@@ -0,0 +1,99 @@
+use std::fmt;
+
+use tracing::{Event, Subscriber};
+use tracing_subscriber::{
+    fmt::{format::Writer, FmtContext, FormatEvent, FormatFields},
+    registry::LookupSpan,
+};
+
+use crate::EventFormatter;
+
+/// Formatter chosen at runtime, e.g. compact, pretty or JSON depending on the configuration.
+///
+/// `tracing-subscriber` doesn't implement [`FormatEvent`] for `Box<dyn FormatEvent>`,
+/// this wrapper does, so that it can be wrapped by an [`EventFormatter`], see [`BoxedEventFormatter`].
+pub struct BoxedFormatter<S, N>(Box<dyn FormatEvent<S, N> + Send + Sync>)
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static;
+
+/// [`EventFormatter`] wrapping a formatter chosen at runtime
+pub type BoxedEventFormatter<const VISITOR_SIZE: usize, S, N, T> =
+    EventFormatter<VISITOR_SIZE, BoxedFormatter<S, N>, T>;
+
+impl<S, N> BoxedFormatter<S, N>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    pub fn new(formatter: impl FormatEvent<S, N> + Send + Sync + 'static) -> Self {
+        BoxedFormatter(Box::new(formatter))
+    }
+}
+
+impl<S, N> From<Box<dyn FormatEvent<S, N> + Send + Sync>> for BoxedFormatter<S, N>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn from(formatter: Box<dyn FormatEvent<S, N> + Send + Sync>) -> Self {
+        BoxedFormatter(formatter)
+    }
+}
+
+impl<S, N> FormatEvent<S, N> for BoxedFormatter<S, N>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        self.0.format_event(ctx, writer, event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::Level;
+    use tracing_subscriber::{
+        fmt::{self, format::DefaultFields, FormatEvent},
+        Registry,
+    };
+
+    use super::{BoxedEventFormatter, BoxedFormatter};
+    use crate::tests::capture_with;
+
+    #[test]
+    fn chosen_at_runtime() {
+        let capture = |config: &str| {
+            let formatter = match config {
+                "compact" => BoxedFormatter::new(fmt::format().compact().without_time()),
+                // formatters already boxed elsewhere are accepted as they are
+                _ => {
+                    let boxed: Box<dyn FormatEvent<Registry, DefaultFields> + Send + Sync> =
+                        Box::new(fmt::format().json().without_time());
+                    BoxedFormatter::from(boxed)
+                }
+            };
+            let formatter =
+                BoxedEventFormatter::<10, Registry, DefaultFields, _>::new(formatter, |_| {
+                    Some(Level::WARN)
+                });
+            capture_with(DefaultFields::new(), formatter, || tracing::error!("test"))
+        };
+
+        assert_eq!(
+            capture("compact"),
+            " WARN tracing_rewrite::boxed::tests: test\n"
+        );
+        assert!(
+            capture("json").starts_with("{\"level\":\"WARN\""),
+            "{}",
+            capture("json")
+        );
+    }
+}
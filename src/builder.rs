@@ -0,0 +1,793 @@
+use std::{
+    backtrace::{Backtrace, BacktraceStatus},
+    marker::PhantomData,
+};
+
+use tracing::{field::Field, Dispatch, Level, Metadata, Subscriber};
+use tracing_subscriber::{
+    fmt::{FmtContext, FormatFields},
+    registry::LookupSpan,
+};
+
+use crate::{
+    throttle::Sampling, Decision, EventFormatter, FieldSnapshot, MetadataStrategy, OnRewrite,
+    Overflow, PooledText, PostFormat, RateLimit, RewriteHandle, RewritePolicy, SpanCheck,
+    TextBuffer, Transform, WithContext, WithFields, DEFAULT_VISITOR_SIZE,
+};
+
+/// Builds an [`EventFormatter`] one piece at a time, see [`EventFormatter::builder`].
+///
+/// Formatter and check start as `()` and must both be set before the result is usable as a formatter.
+pub struct EventFormatterBuilder<const VISITOR_SIZE: usize, F, T, B = PooledText> {
+    formatter: F,
+    check: T,
+    options: Options,
+    text: PhantomData<fn() -> B>,
+}
+
+// what doesn't depend on the type parameters, so that changing them moves it as a whole
+struct Options {
+    handle: RewriteHandle,
+    rate_limit: Option<RateLimit>,
+    sampling: Option<Sampling>,
+    transform: Option<Box<Transform>>,
+    on_rewrite: Option<Box<OnRewrite>>,
+    rewrite_spans: bool,
+    capture_fields: Option<&'static [&'static str]>,
+    backtrace: Option<Level>,
+    span_check: Option<Box<SpanCheck>>,
+    post_format: Option<Box<PostFormat>>,
+    tee: bool,
+    metadata_strategy: MetadataStrategy,
+    overflow: Overflow,
+    max_field_len: Option<usize>,
+    forward: Option<Dispatch>,
+}
+
+impl EventFormatter<DEFAULT_VISITOR_SIZE, (), ()> {
+    /// Starts building an [`EventFormatter`] with a capacity of [`DEFAULT_VISITOR_SIZE`] fields,
+    /// useful when the formatter and the check are decided in different places.
+    pub fn builder() -> EventFormatterBuilder<DEFAULT_VISITOR_SIZE, (), ()> {
+        EventFormatterBuilder {
+            formatter: (),
+            check: (),
+            options: Options {
+                handle: RewriteHandle::default(),
+                rate_limit: None,
+                sampling: None,
+                transform: None,
+                on_rewrite: None,
+                rewrite_spans: true,
+                capture_fields: None,
+                backtrace: None,
+                span_check: None,
+                post_format: None,
+                tee: false,
+                metadata_strategy: MetadataStrategy::default(),
+                overflow: Overflow::Truncate,
+                max_field_len: None,
+                forward: None,
+            },
+            text: PhantomData,
+        }
+    }
+}
+
+impl<const VISITOR_SIZE: usize, F, T, B> EventFormatterBuilder<VISITOR_SIZE, F, T, B> {
+    /// Sets the formatter receiving the rewritten events
+    pub fn formatter<F2>(self, formatter: F2) -> EventFormatterBuilder<VISITOR_SIZE, F2, T, B> {
+        EventFormatterBuilder {
+            formatter,
+            check: self.check,
+            options: self.options,
+            text: self.text,
+        }
+    }
+
+    /// Sets the check deciding the rewrite, like the one given to [`EventFormatter::new`]
+    pub fn check<T2, R>(self, check: T2) -> EventFormatterBuilder<VISITOR_SIZE, F, T2, B>
+    where
+        T2: Fn(&Metadata<'static>) -> R + Send + Sync,
+        R: Into<Decision>,
+    {
+        self.policy(check)
+    }
+
+    /// Sets the [`RewritePolicy`] deciding the rewrite, for policies implemented by named types
+    pub fn policy<P: RewritePolicy>(
+        self,
+        policy: P,
+    ) -> EventFormatterBuilder<VISITOR_SIZE, F, P, B> {
+        EventFormatterBuilder {
+            formatter: self.formatter,
+            check: policy,
+            options: self.options,
+            text: self.text,
+        }
+    }
+
+    /// Sets a check that can also look at the values of the event,
+    /// like the one given to [`EventFormatter::with_fields`]
+    pub fn check_with_fields<T2, R>(
+        self,
+        check: T2,
+    ) -> EventFormatterBuilder<VISITOR_SIZE, F, WithFields<T2>, B>
+    where
+        T2: Fn(&Metadata<'static>, &FieldSnapshot<'_>) -> R + Send + Sync,
+        R: Into<Decision>,
+    {
+        EventFormatterBuilder {
+            formatter: self.formatter,
+            check: WithFields(check),
+            options: self.options,
+            text: self.text,
+        }
+    }
+
+    /// Sets a check that can also look at the formatting context,
+    /// like the one given to [`EventFormatter::with_context`]
+    pub fn check_with_context<T2, S, N, R>(
+        self,
+        check: T2,
+    ) -> EventFormatterBuilder<VISITOR_SIZE, F, WithContext<T2>, B>
+    where
+        T2: Fn(&Metadata<'static>, &FmtContext<'_, S, N>) -> R + Send + Sync,
+        S: Subscriber + for<'a> LookupSpan<'a>,
+        N: for<'a> FormatFields<'a> + 'static,
+        R: Into<Decision>,
+    {
+        EventFormatterBuilder {
+            formatter: self.formatter,
+            check: WithContext(check),
+            options: self.options,
+            text: self.text,
+        }
+    }
+
+    /// Sets how many fields are kept for every rewritten event
+    pub fn visitor_size<const SIZE: usize>(self) -> EventFormatterBuilder<SIZE, F, T, B> {
+        EventFormatterBuilder {
+            formatter: self.formatter,
+            check: self.check,
+            options: self.options,
+            text: self.text,
+        }
+    }
+
+    /// Shares `handle` with other formatters, so that a single switch turns rewriting on and off
+    pub fn handle(self, handle: RewriteHandle) -> Self {
+        EventFormatterBuilder {
+            options: Options {
+                handle,
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Limits how many events of every callsite get rewritten, see [`RateLimit`]
+    pub fn rate_limit(self, rate_limit: RateLimit) -> Self {
+        EventFormatterBuilder {
+            options: Options {
+                rate_limit: Some(rate_limit),
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Rewrites only one every `n` matching events of every callsite, the others keep their original metadata.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is 0.
+    pub fn sample(self, n: u32) -> Self {
+        EventFormatterBuilder {
+            options: Options {
+                sampling: Some(Sampling::new(n)),
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Rewrites the values of the matched events, see [`EventFormatter::with_transform`]
+    pub fn transform(
+        self,
+        transform: impl Fn(&Field, &str) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        EventFormatterBuilder {
+            options: Options {
+                transform: Some(Box::new(transform)),
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Records only the fields named in `names` for the check set with [`check_with_fields`](Self::check_with_fields),
+    /// so that wide events not matching the check cost less.
+    ///
+    /// Matching events are recorded again in full, so that the rewritten event keeps all its fields.
+    pub fn capture_fields(self, names: &'static [&'static str]) -> Self {
+        EventFormatterBuilder {
+            options: Options {
+                capture_fields: Some(names),
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Sets whether span lifecycle events, enabled with `with_span_events`, are rewritten too,
+    /// `true` by default: when `false` they go straight to the formatter without calling the check
+    pub fn rewrite_spans(self, rewrite_spans: bool) -> Self {
+        EventFormatterBuilder {
+            options: Options {
+                rewrite_spans,
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Attaches a [`BACKTRACE_FIELD_NAME`](crate::BACKTRACE_FIELD_NAME) field to the events whose level
+    /// gets raised to `threshold` or above it, e.g. `Level::ERROR` to capture it only when a `warn!`
+    /// becomes an `error!`.
+    ///
+    /// The field is attached only if `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE` enable
+    /// [`Backtrace::capture`](std::backtrace::Backtrace::capture) when the formatter is built,
+    /// otherwise no field is injected at all.
+    #[cfg(feature = "backtrace")]
+    pub fn backtrace(self, threshold: Level) -> Self {
+        EventFormatterBuilder {
+            options: Options {
+                backtrace: Some(threshold),
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Sets the check deciding the rewrite of span lifecycle events, enabled with `with_span_events`,
+    /// so that e.g. the enter and exit logs of a `TRACE` span are formatted as `DEBUG`.
+    ///
+    /// Span lifecycle events are handed only to this check, the one set with [`check`](Self::check)
+    /// sees only events, and don't get rewritten at all with [`rewrite_spans(false)`](Self::rewrite_spans).
+    pub fn check_spans<R>(
+        self,
+        check: impl Fn(&Metadata<'static>) -> R + Send + Sync + 'static,
+    ) -> Self
+    where
+        R: Into<Decision>,
+    {
+        EventFormatterBuilder {
+            options: Options {
+                span_check: Some(Box::new(move |metadata| check(metadata).into())),
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Calls `on_rewrite` with the original and the new level of every event actually rewritten,
+    /// before formatting it, e.g. to count promotions and demotions.
+    ///
+    /// Events left untouched by sampling, rate limiting or [`Rewrite::DROP`](crate::Rewrite::DROP) don't trigger it.
+    pub fn on_rewrite(
+        self,
+        on_rewrite: impl Fn(&Metadata<'_>, Level, Level) + Send + Sync + 'static,
+    ) -> Self {
+        EventFormatterBuilder {
+            options: Options {
+                on_rewrite: Some(Box::new(on_rewrite)),
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Calls `post_format` with the formatted output of every rewritten event, newline included,
+    /// before it reaches the writer, e.g. to append a correlation id kept in a thread-local.
+    ///
+    /// Only rewritten events are buffered, and without ANSI colors: the others are written
+    /// straight to the writer, as without the hook.
+    pub fn post_format(self, post_format: impl Fn(&mut String) + Send + Sync + 'static) -> Self {
+        EventFormatterBuilder {
+            options: Options {
+                post_format: Some(Box::new(post_format)),
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Sets whether matched events are written twice, first untouched and then rewritten,
+    /// `false` by default: e.g. to compare the two while migrating rules.
+    ///
+    /// Both are written only for events actually rewritten, not for the ones left untouched by
+    /// sampling or rate limiting, nor for dropped ones. If writing the original event fails,
+    /// the rewritten one isn't written and the error is returned.
+    pub fn tee(self, tee: bool) -> Self {
+        EventFormatterBuilder {
+            options: Options {
+                tee,
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Sets how rewritten metadata is allocated and released, see [`MetadataStrategy`] for the
+    /// tradeoffs, the default depends on the enabled features
+    pub fn metadata_strategy(self, metadata_strategy: MetadataStrategy) -> Self {
+        EventFormatterBuilder {
+            options: Options {
+                metadata_strategy,
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Sets what happens to rewritten events with more fields than `VISITOR_SIZE`,
+    /// [`Overflow::Truncate`] by default.
+    ///
+    /// Injected fields count too, like the original level, a backtrace or static fields,
+    /// since they take the place of the last recorded values.
+    pub fn on_overflow(self, overflow: Overflow) -> Self {
+        EventFormatterBuilder {
+            options: Options {
+                overflow,
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Cuts string and `Debug` values of rewritten events to `max_len` bytes, followed by `…`,
+    /// so that huge structs or collections don't blow up log lines; values are cut on character
+    /// boundaries, so they can be slightly shorter than `max_len`.
+    ///
+    /// Values are cut while being recorded, the formatting of the rest of the value is skipped,
+    /// checks set with [`check_with_fields`](Self::check_with_fields) see them cut too.
+    /// Events left untouched are formatted as they are.
+    pub fn max_field_len(self, max_len: usize) -> Self {
+        EventFormatterBuilder {
+            options: Options {
+                max_field_len: Some(max_len),
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Dispatches every rewritten event to `sink` too, e.g. an alerting subscriber,
+    /// right before formatting it as usual.
+    ///
+    /// `sink` receives the event as rewritten, with the rewritten metadata, when it's enabled for it.
+    /// Since the event is handed over from inside the formatter, a few things to keep in mind:
+    /// - `sink` may keep the `&'static` metadata of the event, e.g. to batch events on another
+    ///   thread, so it's never freed: with [`MetadataStrategy::Free`], the default without the
+    ///   leaking features, the formatter uses [`MetadataStrategy::Cache`] instead, leaking the
+    ///   metadata once per callsite and rewrite
+    /// - `sink` runs on the thread emitting the event, while the formatting subscriber is busy:
+    ///   it should be quick, or hand the event over to another thread
+    /// - what `sink` logs while handling the event is discarded by `tracing` with a scoped default
+    ///   subscriber, but reaches the global one otherwise, this formatter included
+    /// - events rewritten while forwarding, by this formatter or by an `EventFormatter` of `sink`,
+    ///   aren't forwarded again, so neither of them nor subscribers forwarding to each other recurse
+    /// - span ids belong to the formatting subscriber: `sink` doesn't know the explicit parent
+    ///   of an event, and sees its own current span rather than the one of the event
+    pub fn forward_to(self, sink: impl Into<Dispatch>) -> Self {
+        EventFormatterBuilder {
+            options: Options {
+                forward: Some(sink.into()),
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Sets the buffer string and `Debug` values are formatted into, e.g. [`StackText`](crate::StackText)
+    /// to never allocate
+    pub fn text_buffer<B2: TextBuffer>(self) -> EventFormatterBuilder<VISITOR_SIZE, F, T, B2> {
+        EventFormatterBuilder {
+            formatter: self.formatter,
+            check: self.check,
+            options: self.options,
+            text: PhantomData,
+        }
+    }
+
+    pub fn build(self) -> EventFormatter<VISITOR_SIZE, F, T, B> {
+        const { assert!(VISITOR_SIZE > 0, "VISITOR_SIZE must be at least 1") };
+        let Options {
+            handle,
+            rate_limit,
+            sampling,
+            transform,
+            on_rewrite,
+            rewrite_spans,
+            capture_fields,
+            backtrace,
+            span_check,
+            post_format,
+            tee,
+            metadata_strategy,
+            overflow,
+            max_field_len,
+            forward,
+        } = self.options;
+        // forwarded events can outlive their formatting, see `forward_to`
+        let metadata_strategy = match metadata_strategy {
+            MetadataStrategy::Free if forward.is_some() => MetadataStrategy::Cache,
+            metadata_strategy => metadata_strategy,
+        };
+        EventFormatter {
+            formatter: self.formatter,
+            check: self.check,
+            handle,
+            rate_limit,
+            sampling,
+            transform,
+            on_rewrite,
+            rewrite_spans,
+            capture_fields,
+            backtrace: capturing(backtrace, Backtrace::capture),
+            span_check,
+            post_format,
+            tee,
+            metadata_strategy,
+            overflow,
+            max_field_len,
+            forward,
+            static_fields: Default::default(),
+            text: self.text,
+        }
+    }
+}
+
+// keeps the backtrace threshold only if `probe` actually captures, so that a disabled backtrace
+// never takes a field of the visitor
+fn capturing(threshold: Option<Level>, probe: fn() -> Backtrace) -> Option<Level> {
+    threshold.filter(|_| probe().status() == BacktraceStatus::Captured)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tracing::{Event, Level, Subscriber};
+    use tracing_subscriber::{
+        fmt::{
+            self,
+            format::{DefaultFields, Writer},
+            FmtContext, FormatEvent, FormatFields,
+        },
+        registry::LookupSpan,
+    };
+
+    use crate::{tests::capture_with, EventFormatter, Overflow, Rewrite, TRUNCATED_FIELD_NAME};
+
+    // logs what happens, in order
+    #[derive(Clone, Default)]
+    struct Log(Arc<Mutex<Vec<String>>>);
+
+    impl Log {
+        fn push(&self, line: String) {
+            self.0.lock().unwrap().push(line);
+        }
+    }
+
+    impl<S, N> FormatEvent<S, N> for Log
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+        N: for<'a> FormatFields<'a> + 'static,
+    {
+        fn format_event(
+            &self,
+            _: &FmtContext<'_, S, N>,
+            _: Writer<'_>,
+            event: &Event<'_>,
+        ) -> std::fmt::Result {
+            self.push(format!("format {}", event.metadata().level()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn on_rewrite() {
+        let log = Log::default();
+        let hook = log.clone();
+        let formatter = EventFormatter::builder()
+            .formatter(log.clone())
+            .check(|metadata| match *metadata.level() {
+                Level::ERROR => Some(Rewrite::from(Level::WARN)),
+                Level::INFO => Some(Rewrite::default()),
+                Level::DEBUG => Some(Rewrite::DROP),
+                _ => None,
+            })
+            .on_rewrite(move |metadata, from, to| {
+                assert_eq!(*metadata.level(), from);
+                hook.push(format!("rewrite {from} -> {to}"));
+            })
+            .build();
+
+        capture_with(DefaultFields::new(), formatter, || {
+            tracing::error!("rewritten");
+            tracing::warn!("untouched");
+            tracing::info!("same level");
+            tracing::debug!("dropped");
+        });
+
+        assert_eq!(
+            *log.0.lock().unwrap(),
+            [
+                "rewrite ERROR -> WARN",
+                "format WARN",
+                "format WARN",
+                "rewrite INFO -> INFO",
+                "format INFO",
+            ]
+        );
+    }
+
+    #[test]
+    fn builder() {
+        let builder = EventFormatter::builder().check(|_| Some(Level::WARN));
+        // formatter decided somewhere else
+        let builder = builder.formatter(fmt::format().compact().without_time());
+
+        let output = capture_with(
+            DefaultFields::new(),
+            builder.visitor_size::<2>().build(),
+            || tracing::error!(a = 1, b = 2, "test"),
+        );
+
+        assert_eq!(
+            output,
+            format!(" WARN tracing_rewrite::builder::tests: test {TRUNCATED_FIELD_NAME}=2\n")
+        );
+    }
+
+    #[test]
+    fn capture_fields() {
+        let formatter = EventFormatter::builder()
+            .formatter(fmt::format().compact().without_time())
+            .check_with_fields(|_, fields| {
+                assert!(fields.get("path").is_none());
+                (fields.get("status")?.as_u64()? >= 500).then_some(Level::ERROR)
+            })
+            .capture_fields(&["status"])
+            .build();
+
+        let output = capture_with(DefaultFields::new(), formatter, || {
+            tracing::info!(path = "/", status = 200, "ok");
+            tracing::info!(path = "/", status = 503, "unavailable");
+        });
+
+        assert_eq!(
+            output,
+            " INFO tracing_rewrite::builder::tests: ok path=\"/\" status=200\nERROR \
+             tracing_rewrite::builder::tests: unavailable path=\"/\" status=503\n"
+        );
+    }
+
+    // formats a promoted event, one left at its level and one already at the threshold,
+    // as if the environment had enabled backtraces when `probe` captures
+    #[cfg(feature = "backtrace")]
+    fn with_backtrace(probe: fn() -> std::backtrace::Backtrace) -> String {
+        let mut formatter = EventFormatter::builder()
+            .formatter(fmt::format().compact().without_time())
+            .check(|metadata| match *metadata.level() {
+                Level::WARN | Level::ERROR => Some(Level::ERROR),
+                Level::DEBUG => Some(Level::WARN),
+                _ => None,
+            })
+            .backtrace(Level::ERROR)
+            .build();
+        formatter.backtrace = super::capturing(Some(Level::ERROR), probe);
+
+        capture_with(DefaultFields::new(), formatter, || {
+            tracing::warn!(a = 1, "promoted");
+            tracing::debug!("below");
+            tracing::error!("same");
+        })
+    }
+
+    #[cfg(feature = "backtrace")]
+    #[test]
+    fn backtrace() {
+        let output = with_backtrace(std::backtrace::Backtrace::force_capture);
+
+        let (promoted, rest) = output.split_once('\n').unwrap();
+        let frames = promoted
+            .strip_prefix("ERROR tracing_rewrite::builder::tests: promoted a=1 backtrace=")
+            .unwrap_or_else(|| panic!("{output}"));
+        assert!(frames.contains("with_backtrace"), "{output}");
+        assert_eq!(
+            rest,
+            " WARN tracing_rewrite::builder::tests: below\nERROR \
+             tracing_rewrite::builder::tests: same\n"
+        );
+    }
+
+    #[cfg(feature = "backtrace")]
+    #[test]
+    fn backtrace_disabled() {
+        let output = with_backtrace(std::backtrace::Backtrace::disabled);
+
+        // no field at all, rather than a `disabled backtrace` one
+        assert_eq!(
+            output,
+            "ERROR tracing_rewrite::builder::tests: promoted a=1\n WARN \
+             tracing_rewrite::builder::tests: below\nERROR tracing_rewrite::builder::tests: \
+             same\n"
+        );
+    }
+
+    #[test]
+    fn post_format() {
+        thread_local! {
+            static CORRELATION_ID: &'static str = const { "abc" };
+        }
+
+        let formatter = EventFormatter::builder()
+            .formatter(fmt::format().compact().without_time())
+            .check(|metadata| (*metadata.level() == Level::ERROR).then_some(Level::WARN))
+            .post_format(|output| {
+                let id = CORRELATION_ID.with(|id| *id);
+                output.insert_str(output.len() - 1, &format!(" correlation_id={id}"));
+            })
+            .build();
+
+        let output = capture_with(DefaultFields::new(), formatter, || {
+            tracing::error!("rewritten");
+            tracing::info!("untouched");
+        });
+
+        assert_eq!(
+            output,
+            " WARN tracing_rewrite::builder::tests: rewritten correlation_id=abc\n INFO \
+             tracing_rewrite::builder::tests: untouched\n"
+        );
+    }
+
+    #[test]
+    fn tee() {
+        let formatter = EventFormatter::builder()
+            .formatter(fmt::format().compact().without_time())
+            .check(|metadata| (*metadata.level() == Level::ERROR).then_some(Level::WARN))
+            .tee(true)
+            .build();
+
+        let output = capture_with(DefaultFields::new(), formatter, || {
+            tracing::error!(a = 1, "rewritten");
+            tracing::info!("untouched");
+        });
+
+        assert_eq!(
+            output,
+            "ERROR tracing_rewrite::builder::tests: rewritten a=1\n WARN \
+             tracing_rewrite::builder::tests: rewritten a=1\n INFO \
+             tracing_rewrite::builder::tests: untouched\n"
+        );
+    }
+
+    // fails formatting the events of a level, after logging them
+    struct FailOn(Log, Level);
+
+    impl<S, N> FormatEvent<S, N> for FailOn
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+        N: for<'a> FormatFields<'a> + 'static,
+    {
+        fn format_event(
+            &self,
+            ctx: &FmtContext<'_, S, N>,
+            writer: Writer<'_>,
+            event: &Event<'_>,
+        ) -> std::fmt::Result {
+            self.0.format_event(ctx, writer, event)?;
+            if *event.metadata().level() == self.1 {
+                return Err(std::fmt::Error);
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn tee_stops_at_the_first_error() {
+        let log = Log::default();
+        let formatter = EventFormatter::builder()
+            .formatter(FailOn(log.clone(), Level::ERROR))
+            .check(|metadata| (*metadata.level() == Level::ERROR).then_some(Level::WARN))
+            .tee(true)
+            .build();
+
+        capture_with(DefaultFields::new(), formatter, || tracing::error!("test"));
+
+        // the original event failed, the rewritten one isn't formatted
+        assert_eq!(*log.0.lock().unwrap(), ["format ERROR"]);
+    }
+
+    #[test]
+    fn with_fields() {
+        let formatter = EventFormatter::builder()
+            .formatter(fmt::format().compact().without_time())
+            .check_with_fields(|_, fields| fields.get("a").is_some().then_some(Level::WARN))
+            .build();
+
+        let output = capture_with(DefaultFields::new(), formatter, || {
+            tracing::error!(a = 1, "test");
+            tracing::error!("test");
+        });
+
+        assert!(output.starts_with(" WARN "), "{output}");
+        assert!(output.contains("\nERROR "), "{output}");
+    }
+
+    #[test]
+    fn on_overflow() {
+        let capture = |overflow| {
+            let formatter = EventFormatter::builder()
+                .visitor_size::<3>()
+                .formatter(fmt::format().compact().without_time())
+                .check(|_| {
+                    Some(Rewrite {
+                        level: Some(Level::WARN),
+                        ..Default::default()
+                    })
+                })
+                .on_overflow(overflow)
+                .build();
+            capture_with(DefaultFields::new(), formatter, || {
+                tracing::error!(a = 1, b = 2, c = 3, "wide");
+                tracing::error!(a = 1, b = 2, "narrow");
+            })
+        };
+
+        assert_eq!(
+            capture(Overflow::Truncate),
+            format!(
+                " WARN tracing_rewrite::builder::tests: wide a=1 {TRUNCATED_FIELD_NAME}=2\n WARN \
+                 tracing_rewrite::builder::tests: narrow a=1 b=2\n"
+            )
+        );
+        // the wide event is left as it is, the narrow one still fits
+        assert_eq!(
+            capture(Overflow::Passthrough),
+            "ERROR tracing_rewrite::builder::tests: wide a=1 b=2 c=3\n WARN \
+             tracing_rewrite::builder::tests: narrow a=1 b=2\n"
+        );
+    }
+
+    #[test]
+    fn max_field_len() {
+        let formatter = EventFormatter::builder()
+            .formatter(fmt::format().compact().without_time())
+            .check(|metadata| (*metadata.level() == Level::ERROR).then_some(Level::WARN))
+            .max_field_len(10)
+            .build();
+        // 10KB, made of 2 bytes characters
+        let huge = "é".repeat(5 * 1024);
+        let output = capture_with(DefaultFields::new(), formatter, || {
+            tracing::error!(debug = ?huge, text = huge.as_str(), short = "ok", "test");
+            tracing::info!(untouched = ?huge);
+        });
+
+        // the `Debug` value keeps its opening `"` and 4 characters, the 5th one would cross the limit,
+        // the string keeps 5 characters and is quoted by the formatter afterwards
+        let mut lines = output.lines();
+        assert_eq!(
+            lines.next(),
+            Some(" WARN tracing_rewrite::builder::tests: test debug=\"éééé… text=\"ééééé…\" short=\"ok\"")
+        );
+        // events left untouched aren't cut
+        assert!(lines
+            .next()
+            .unwrap()
+            .ends_with(&format!("untouched=\"{huge}\"")));
+    }
+}
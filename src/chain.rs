@@ -0,0 +1,107 @@
+use tracing::Metadata;
+
+use crate::Rewrite;
+
+/// Combines two checks, `second` is called only when `first` returns `None`.
+///
+/// See [`checks!`](crate::checks) to combine any number of them.
+pub fn chain<A, B, RA, RB>(
+    first: A,
+    second: B,
+) -> impl Fn(&Metadata<'static>) -> Option<Rewrite> + Send + Sync
+where
+    A: Fn(&Metadata<'static>) -> Option<RA> + Send + Sync,
+    B: Fn(&Metadata<'static>) -> Option<RB> + Send + Sync,
+    RA: Into<Rewrite>,
+    RB: Into<Rewrite>,
+{
+    move |metadata| {
+        first(metadata)
+            .map(Into::into)
+            .or_else(|| second(metadata).map(Into::into))
+    }
+}
+
+/// Combines several checks in priority order, the first one returning `Some` wins.
+///
+/// A single check is returned as it is, so there's no cost in using the macro for a single rule.
+///
+/// ```rust
+/// use tracing::Level;
+/// use tracing_rewrite::{checks, EventFormatter};
+/// use tracing_subscriber::fmt;
+///
+/// let formatter = EventFormatter::<10, _, _>::new(
+///     fmt::format(),
+///     checks![
+///         |metadata| metadata.target().starts_with("auth").then_some(Level::ERROR),
+///         |metadata| (metadata.name() == "health").then_some(Level::TRACE),
+///     ],
+/// );
+/// ```
+#[macro_export]
+macro_rules! checks {
+    ($check:expr $(,)?) => {
+        $check
+    };
+    ($first:expr, $($rest:expr),+ $(,)?) => {
+        $crate::chain($first, $crate::checks![$($rest),+])
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::Level;
+    use tracing_subscriber::fmt::{self, format::DefaultFields};
+
+    use crate::{tests::capture, Rewrite};
+
+    #[test]
+    fn first_match_wins() {
+        let output = capture(
+            DefaultFields::new(),
+            fmt::format().compact().without_time(),
+            checks![
+                |metadata| metadata
+                    .fields()
+                    .field("auth")
+                    .is_some()
+                    .then_some(Level::ERROR),
+                |metadata| {
+                    metadata
+                        .fields()
+                        .field("health")
+                        .is_some()
+                        .then_some(Rewrite {
+                            target: Some("health"),
+                            ..Default::default()
+                        })
+                },
+                |_| Some(Level::TRACE),
+            ],
+            || {
+                tracing::warn!(auth = true, health = true, "first");
+                tracing::warn!(health = true, "second");
+                tracing::warn!("third");
+            },
+        );
+
+        assert_eq!(
+            output,
+            "ERROR tracing_rewrite::chain::tests: first auth=true health=true\n WARN health: second \
+             health=true\nTRACE tracing_rewrite::chain::tests: third\n"
+        );
+    }
+
+    #[test]
+    fn single() {
+        let output = capture(
+            DefaultFields::new(),
+            fmt::format().compact().without_time(),
+            checks![|_| Some(Level::TRACE)],
+            || tracing::warn!("test"),
+        );
+
+        assert_eq!(output, "TRACE tracing_rewrite::chain::tests: test\n");
+    }
+}
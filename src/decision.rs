@@ -0,0 +1,133 @@
+use tracing::Level;
+
+use crate::Rewrite;
+
+/// Overrides applied by [`Decision::Rewrite`]: level, target, name, file, line and the other
+/// fields of a [`Rewrite`], together with the fields to inject
+pub type MetadataPatch = Rewrite;
+
+/// What a check decides to do with an event.
+///
+/// Checks can return it directly, or anything converting into it: `Option<Level>`,
+/// `Option<Rewrite>` and every other `Option` of something converting into a [`Rewrite`]
+/// keep working, with `None` meaning [`Decision::Keep`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Decision {
+    /// the event is formatted untouched
+    #[default]
+    Keep,
+    /// only the level of the event changes.
+    ///
+    /// The values are still recorded and re-emitted, since `Event` doesn't expose its `ValueSet`,
+    /// but with the type they were recorded with: numbers, booleans and errors stay such.
+    SetLevel(Level),
+    /// the event isn't written at all, like [`Rewrite::DROP`](crate::Rewrite::DROP)
+    Drop,
+    /// the event gets rewritten as described by the patch
+    Rewrite(MetadataPatch),
+    /// the original event is formatted as it is, only its output is decorated
+    Decorate(Decoration),
+}
+
+/// Presentational changes applied by [`Decision::Decorate`], e.g. to indent or color an event.
+///
+/// The original event reaches the formatter untouched, level included, skipping the rewrite of
+/// its metadata entirely: the formatter only gets a writer wrapping the real one.
+/// Like the markers of a [`Rewrite`], indentation and suffix hide ANSI support from the formatter.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Decoration {
+    /// written at the start of every line of the output
+    pub indent: Option<&'static str>,
+    /// written before the output, e.g. an ANSI color code
+    pub prefix: Option<&'static str>,
+    /// written before the trailing newline of the output, e.g. the ANSI reset code
+    pub suffix: Option<&'static str>,
+}
+
+impl Decision {
+    // the rewrite applied to the event, `None` when it's formatted untouched
+    pub(crate) fn into_rewrite(self) -> Option<Rewrite> {
+        match self {
+            Decision::Keep => None,
+            Decision::SetLevel(level) => Some(level.into()),
+            Decision::Drop => Some(Rewrite::DROP),
+            Decision::Rewrite(patch) => Some(patch),
+            // decorations need a writer, without one the event is left untouched
+            Decision::Decorate(_) => None,
+        }
+    }
+}
+
+impl From<Level> for Decision {
+    fn from(level: Level) -> Self {
+        Decision::SetLevel(level)
+    }
+}
+
+impl From<Rewrite> for Decision {
+    fn from(rewrite: Rewrite) -> Self {
+        Decision::Rewrite(rewrite)
+    }
+}
+
+impl<R: Into<Rewrite>> From<Option<R>> for Decision {
+    fn from(rewrite: Option<R>) -> Self {
+        rewrite.map_or(Decision::Keep, |rewrite| Decision::Rewrite(rewrite.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::Level;
+    use tracing_subscriber::fmt::{self, format::DefaultFields};
+
+    use super::Decision;
+    use crate::{tests::capture_with, EventFormatter, Rewrite};
+
+    #[test]
+    fn decisions() {
+        let formatter =
+            EventFormatter::<10, _, _>::new(fmt::format().compact().without_time(), |metadata| {
+                match metadata.name() {
+                    name if name.contains("keep") => Decision::Keep,
+                    name if name.contains("drop") => Decision::Drop,
+                    name if name.contains("level") => Decision::SetLevel(Level::WARN),
+                    _ => Decision::Rewrite(Rewrite {
+                        target: Some("patched"),
+                        line: Some(1),
+                        ..Default::default()
+                    }),
+                }
+            });
+
+        let output = capture_with(DefaultFields::new(), formatter, || {
+            tracing::error!(name: "keep", "kept");
+            tracing::error!(name: "drop", "dropped");
+            tracing::error!(name: "level", "demoted");
+            tracing::error!(name: "patch", "patched");
+        });
+
+        assert_eq!(
+            output,
+            "ERROR tracing_rewrite::decision::tests: kept\n WARN tracing_rewrite::decision::tests: \
+             demoted\nERROR patched: patched\n"
+        );
+    }
+
+    #[test]
+    fn options_convert() {
+        assert_eq!(Decision::from(None::<Level>), Decision::Keep);
+        assert_eq!(
+            Decision::from(Some(Level::INFO)),
+            Decision::Rewrite(Level::INFO.into())
+        );
+        assert_eq!(
+            Decision::from(Some(Rewrite::DROP)).into_rewrite(),
+            Some(Rewrite::DROP)
+        );
+        assert_eq!(
+            Decision::from(Level::INFO).into_rewrite(),
+            Some(Level::INFO.into())
+        );
+    }
+}
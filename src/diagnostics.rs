@@ -0,0 +1,118 @@
+use tracing::{field::ValueSet, Metadata};
+
+/// Checks that `valueset` holds the `recorded` values, all of them belonging to `metadata`.
+///
+/// `ValueSet` ignores values whose field belongs to another callsite, so a field copied wrongly
+/// shows up as a missing value: on mismatch the caller formats the original event instead,
+/// and a warning is printed to stderr the first time it happens.
+#[cfg(feature = "diagnostics")]
+pub fn consistent(valueset: &ValueSet<'_>, recorded: usize, metadata: &Metadata<'_>) -> bool {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    static WARNED: AtomicBool = AtomicBool::new(false);
+
+    let len = valueset.len();
+    // span lifecycle events carry `message` and `time.*` values of a `FieldSet` of their own,
+    // sharing the callsite of the span, so they can outnumber the fields of the metadata
+    let consistent = len == recorded && (metadata.is_span() || len <= metadata.fields().len());
+    if !consistent && !WARNED.swap(true, Ordering::Relaxed) {
+        eprintln!(
+            "tracing-rewrite: rewritten event {:?} of {} kept {len} of {recorded} recorded values \
+             ({} fields), formatting the original event. This warning is printed only once.",
+            metadata.name(),
+            metadata.target(),
+            metadata.fields().len(),
+        );
+    }
+    consistent
+}
+
+#[cfg(not(feature = "diagnostics"))]
+pub fn consistent(_: &ValueSet<'_>, _: usize, _: &Metadata<'_>) -> bool {
+    true
+}
+
+/// Checks that the `FieldSet` of `rewritten`, copied from `original`, still belongs to the
+/// callsite of `original` and starts with its fields.
+///
+/// The copy relies on the private layout of `tracing-core` types, unless `safe_fields` is enabled:
+/// when an incompatible `tracing-core` breaks it, the caller formats the original event instead
+/// of a rewritten one pointing at garbage, and a warning is printed to stderr the first time it happens.
+/// Enabled in debug builds and with the `diagnostics` feature, release builds skip it.
+#[cfg(any(debug_assertions, feature = "diagnostics"))]
+pub fn fields_valid(original: &Metadata<'_>, rewritten: &Metadata<'_>) -> bool {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    static WARNED: AtomicBool = AtomicBool::new(false);
+
+    let (from, to) = (original.fields(), rewritten.fields());
+    let valid = to
+        .iter()
+        .all(|field| field.callsite() == original.callsite())
+        && to.len() >= from.len()
+        && from
+            .iter()
+            .zip(to.iter())
+            .all(|(a, b)| a.name() == b.name());
+    if !valid && !WARNED.swap(true, Ordering::Relaxed) {
+        eprintln!(
+            "tracing-rewrite: the fields of rewritten event {:?} of {} don't match the original \
+             callsite, formatting the original event. The layout of tracing-core may have changed, \
+             enable the `safe_fields` feature. This warning is printed only once.",
+            original.name(),
+            original.target(),
+        );
+    }
+    valid
+}
+
+#[cfg(not(any(debug_assertions, feature = "diagnostics")))]
+pub fn fields_valid(_: &Metadata<'_>, _: &Metadata<'_>) -> bool {
+    true
+}
+
+#[cfg(all(test, any(debug_assertions, feature = "diagnostics")))]
+mod tests {
+    use crate::tests::TEST_META;
+
+    #[test]
+    fn invalid_fields() {
+        use tracing::Metadata;
+
+        use crate::tests::TEST_CALLSITE;
+
+        // what a broken copy could look like: same callsite, different fields
+        static SHUFFLED_META: Metadata<'static> = tracing_core::metadata! {
+            name: "test",
+            target: module_path!(),
+            level: tracing::Level::INFO,
+            fields: &["second", "first"],
+            callsite: &TEST_CALLSITE,
+            kind: tracing_core::Kind::EVENT,
+        };
+
+        let rewritten = crate::MetadataStrategy::Free.rewritten(
+            &TEST_META,
+            &Default::default(),
+            Default::default(),
+        );
+        assert!(super::fields_valid(&TEST_META, rewritten.get()));
+        assert!(!super::fields_valid(&TEST_META, &SHUFFLED_META));
+        assert!(!super::fields_valid(&SHUFFLED_META, &TEST_META));
+    }
+
+    #[cfg(feature = "diagnostics")]
+    #[test]
+    fn mismatch() {
+        use tracing::Value;
+
+        let fields = TEST_META.fields();
+        let first = fields.field("first").unwrap();
+        let values = [(&first, Some(&1 as &dyn Value))];
+        let valueset = fields.value_set(&values);
+
+        assert!(super::consistent(&valueset, 1, &TEST_META));
+        // a value lost on the way
+        assert!(!super::consistent(&valueset, 2, &TEST_META));
+    }
+}
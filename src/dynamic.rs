@@ -0,0 +1,175 @@
+use tracing::{Event, Metadata, Subscriber};
+use tracing_subscriber::{
+    fmt::{format::Writer, FmtContext, FormatEvent, FormatFields},
+    registry::LookupSpan,
+};
+
+use crate::{
+    diagnostics, marker, metadata, new_event, policy, visitor, Decision, MetadataStrategy,
+};
+
+/// Same as [`EventFormatter`](crate::EventFormatter), but without a fixed `VISITOR_SIZE`:
+/// values are recorded inline up to `INLINE` fields, events with more fields spill to the heap
+/// instead of being truncated.
+pub struct DynEventFormatter<F, T, const INLINE: usize = 8> {
+    formatter: F,
+    check: T,
+}
+
+impl<F, T, R> DynEventFormatter<F, T>
+where
+    T: Fn(&Metadata<'static>) -> R + Send + Sync,
+    R: Into<Decision>,
+{
+    pub fn new(formatter: F, check: T) -> Self {
+        Self { formatter, check }
+    }
+}
+
+impl<F, T, const INLINE: usize> DynEventFormatter<F, T, INLINE> {
+    /// Sets how many fields are recorded without allocating, 8 by default
+    pub fn inline_capacity<const SIZE: usize>(self) -> DynEventFormatter<F, T, SIZE> {
+        DynEventFormatter {
+            formatter: self.formatter,
+            check: self.check,
+        }
+    }
+}
+
+impl<F, T, R, S, N, const INLINE: usize> FormatEvent<S, N> for DynEventFormatter<F, T, INLINE>
+where
+    F: FormatEvent<S, N>,
+    T: Fn(&Metadata<'static>) -> R + Send + Sync,
+    R: Into<Decision>,
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> std::fmt::Result {
+        let metadata = event.metadata();
+
+        let decision =
+            policy::thread_policy(metadata).unwrap_or_else(|| (self.check)(metadata).into());
+        if let Decision::Decorate(decoration) = decision {
+            return marker::with_decoration(&decoration, writer, |writer| {
+                self.formatter.format_event(ctx, writer, event)
+            });
+        }
+        if let Some(rewrite) = decision.into_rewrite() {
+            if rewrite.drop {
+                return Ok(());
+            }
+
+            let mut visitor = visitor::DynVisitor::<INLINE>::new(event.fields().count());
+            event.record(&mut visitor);
+
+            let injected = metadata::Injected {
+                original_level: rewrite.original_level,
+                ..Default::default()
+            };
+            let rewritten = MetadataStrategy::default().rewritten(metadata, &rewrite, injected);
+            let metadata = rewritten.get();
+            if !diagnostics::fields_valid(event.metadata(), metadata) {
+                return self.formatter.format_event(ctx, writer, event);
+            }
+            if injected.original_level {
+                let field = metadata
+                    .fields()
+                    .iter()
+                    .nth(event.metadata().fields().len());
+                visitor.record_str(field.unwrap(), event.metadata().level().as_str());
+            }
+
+            marker::with_markers(&rewrite, writer, |writer| {
+                marker::with_trace(event.metadata(), &rewrite, writer, |writer| {
+                    let recorded = visitor.recorded();
+                    visitor.with_value_set(metadata.fields(), |valueset| {
+                        if !diagnostics::consistent(valueset, recorded, metadata) {
+                            return self.formatter.format_event(ctx, writer, event);
+                        }
+                        self.formatter.format_event(
+                            ctx,
+                            writer,
+                            &new_event(event, metadata, valueset),
+                        )
+                    })
+                })
+            })
+        } else {
+            self.formatter.format_event(ctx, writer, event)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::Level;
+    use tracing_subscriber::fmt;
+
+    use super::DynEventFormatter;
+    use crate::tests::capture_with;
+
+    #[test]
+    fn wide_events_are_not_truncated() {
+        let output = capture_with(
+            fmt::format::DefaultFields::new(),
+            DynEventFormatter::new(fmt::format().compact().without_time(), |_| {
+                Some(Level::WARN)
+            })
+            .inline_capacity::<4>(),
+            || {
+                tracing::error!(
+                    a = 1,
+                    b = 2,
+                    c = 3,
+                    d = 4,
+                    e = 5,
+                    f = 6,
+                    g = 7,
+                    h = 8,
+                    i = 9,
+                    j = 10,
+                    k = 11,
+                    l = 12,
+                    m = 13,
+                    n = 14,
+                    o = 15,
+                    p = 16,
+                    q = 17,
+                    "test"
+                )
+            },
+        );
+
+        assert!(
+            output.contains(
+                "WARN tracing_rewrite::dynamic::tests: test a=1 b=2 c=3 d=4 e=5 f=6 g=7 h=8 i=9 \
+                 j=10 k=11 l=12 m=13 n=14 o=15 p=16 q=17\n"
+            ),
+            "{output}"
+        );
+    }
+
+    #[test]
+    fn passthrough() {
+        let emit = || tracing::info!(a = 1, "test");
+        let output = capture_with(
+            fmt::format::DefaultFields::new(),
+            DynEventFormatter::new(fmt::format().compact().without_time(), |_| None::<Level>),
+            emit,
+        );
+        let rewritten = capture_with(
+            fmt::format::DefaultFields::new(),
+            DynEventFormatter::new(fmt::format().compact().without_time(), |_| {
+                Some(Level::INFO)
+            }),
+            emit,
+        );
+
+        assert_eq!(output, rewritten);
+    }
+}
@@ -0,0 +1,153 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use tracing::{callsite::Identifier, Level, Metadata};
+
+use crate::{throttle::Counters, EventFormatter, Predicate};
+
+/// Builds a check promoting the events matched by `predicate` to `level` from the `count`-th
+/// occurrence of their callsite on, e.g. a warning becoming an error the 10th time it happens.
+///
+/// Occurrences are counted per callsite, and only for the events matched by `predicate`.
+/// Counters never reset, see [`escalate_after_within`] to count only the recent occurrences.
+///
+/// ```rust
+/// use tracing::Level;
+/// use tracing_rewrite::{at_level, escalate_after, EventFormatter};
+/// use tracing_subscriber::fmt;
+///
+/// let formatter = EventFormatter::<10, _, _>::new(
+///     fmt::format(),
+///     escalate_after(at_level(Level::WARN), 10, Level::ERROR),
+/// );
+/// ```
+pub fn escalate_after<P>(
+    predicate: P,
+    count: u64,
+    level: Level,
+) -> impl Fn(&Metadata<'static>) -> Option<Level> + Send + Sync
+where
+    P: Predicate + Send + Sync,
+{
+    let counters = Counters::default();
+    move |metadata| {
+        if !predicate.matches(metadata) {
+            return None;
+        }
+        (counters.next(metadata.callsite()) + 1 >= count).then_some(level)
+    }
+}
+
+/// Like [`escalate_after`], but the counter of a callsite resets once `window` has elapsed
+/// since the first occurrence it counted: only `count` occurrences within `window` escalate.
+pub fn escalate_after_within<P>(
+    predicate: P,
+    count: u64,
+    level: Level,
+    window: Duration,
+) -> impl Fn(&Metadata<'static>) -> Option<Level> + Send + Sync
+where
+    P: Predicate + Send + Sync,
+{
+    let windows = Mutex::<HashMap<Identifier, (Instant, u64)>>::default();
+    move |metadata| {
+        if !predicate.matches(metadata) {
+            return None;
+        }
+
+        let now = Instant::now();
+        let mut windows = windows.lock().unwrap_or_else(|e| e.into_inner());
+        let (start, occurrences) = windows.entry(metadata.callsite()).or_insert((now, 0));
+        if now.duration_since(*start) >= window {
+            *start = now;
+            *occurrences = 0;
+        }
+        *occurrences += 1;
+        (*occurrences >= count).then_some(level)
+    }
+}
+
+impl<const VISITOR_SIZE: usize, F> EventFormatter<VISITOR_SIZE, F, ()> {
+    /// Like [`EventFormatter::new`], with the check built by [`escalate_after`]
+    pub fn escalate_after<P>(
+        formatter: F,
+        predicate: P,
+        count: u64,
+        level: Level,
+    ) -> EventFormatter<VISITOR_SIZE, F, impl Fn(&Metadata<'static>) -> Option<Level> + Send + Sync>
+    where
+        P: Predicate + Send + Sync,
+    {
+        EventFormatter::new(formatter, escalate_after(predicate, count, level))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tracing::Level;
+    use tracing_subscriber::fmt::{self, format::DefaultFields};
+
+    use crate::{
+        at_level, escalate_after_within,
+        tests::{capture_with, levels},
+        EventFormatter,
+    };
+
+    #[test]
+    fn escalate_after() {
+        let formatter = EventFormatter::<10, _, _>::escalate_after(
+            fmt::format().compact().without_time(),
+            at_level(Level::WARN),
+            10,
+            Level::ERROR,
+        );
+
+        let output = capture_with(DefaultFields::new(), formatter, || {
+            for _ in 0..11 {
+                tracing::warn!("repeated");
+                // not matched, not counted
+                tracing::info!("info");
+            }
+            // another callsite, counted on its own
+            tracing::warn!("other");
+        });
+
+        let levels = levels(&output);
+        let (repeated, info): (Vec<_>, Vec<_>) =
+            levels[..22].chunks(2).map(|c| (c[0], c[1])).unzip();
+        assert_eq!(repeated[..9], ["WARN"; 9]);
+        assert_eq!(repeated[9..], ["ERROR"; 2]);
+        assert_eq!(info, ["INFO"; 11]);
+        assert_eq!(levels[22], "WARN");
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn window() {
+        let formatter = EventFormatter::<10, _, _>::new(
+            fmt::format().compact().without_time(),
+            escalate_after_within(
+                at_level(Level::WARN),
+                2,
+                Level::ERROR,
+                Duration::from_millis(100),
+            ),
+        );
+
+        let output = capture_with(DefaultFields::new(), formatter, || {
+            let log = || tracing::warn!("test");
+            log();
+            log();
+            std::thread::sleep(Duration::from_millis(150));
+            log();
+            log();
+        });
+
+        assert_eq!(levels(&output), ["WARN", "ERROR", "WARN", "ERROR"]);
+    }
+}
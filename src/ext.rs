@@ -0,0 +1,174 @@
+use tracing::Metadata;
+
+use crate::{Decision, EventFormatter};
+
+/// Any check usable with [`EventFormatter::new`]: a `Fn(&Metadata<'static>)` returning a [`Decision`],
+/// or anything converting into one, like `Option<Level>`.
+///
+/// It only exists to name such checks where the type of their return value can't be a parameter,
+/// every matching closure implements it.
+pub trait Check: Fn(&Metadata<'static>) -> <Self as Check>::Output + Send + Sync {
+    /// What the check returns
+    type Output: Into<Decision>;
+}
+
+impl<T, R> Check for T
+where
+    T: Fn(&Metadata<'static>) -> R + Send + Sync,
+    R: Into<Decision>,
+{
+    type Output = R;
+}
+
+/// A check as a named type, for rewrite policies reused across formatters and unit tested
+/// on their own, without setting up a subscriber:
+///
+/// ```rust
+/// use tracing::{Level, Metadata};
+/// use tracing_rewrite::{EventFormatter, RewritePolicy};
+/// use tracing_subscriber::fmt;
+///
+/// struct Quiet(&'static str);
+///
+/// impl RewritePolicy for Quiet {
+///     type Output = Option<Level>;
+///
+///     fn decide(&self, metadata: &Metadata<'static>) -> Option<Level> {
+///         metadata.target().starts_with(self.0).then_some(Level::DEBUG)
+///     }
+/// }
+///
+/// let formatter = EventFormatter::<16, _, _>::with_policy(fmt::format(), Quiet("noisy"));
+/// ```
+///
+/// Every check usable with [`EventFormatter::new`] is a policy too, so formatters accept either.
+pub trait RewritePolicy: Send + Sync {
+    /// What the policy returns, anything converting into a [`Decision`], like `Option<Level>`
+    type Output: Into<Decision>;
+
+    /// Decides the rewrite of the event or span described by `metadata`
+    fn decide(&self, metadata: &Metadata<'static>) -> Self::Output;
+}
+
+impl<T, R> RewritePolicy for T
+where
+    T: Fn(&Metadata<'static>) -> R + Send + Sync,
+    R: Into<Decision>,
+{
+    type Output = R;
+
+    fn decide(&self, metadata: &Metadata<'static>) -> R {
+        self(metadata)
+    }
+}
+
+impl<const VISITOR_SIZE: usize, F, P: RewritePolicy> EventFormatter<VISITOR_SIZE, F, P> {
+    /// Like [`EventFormatter::new`], with a named [`RewritePolicy`] instead of a closure
+    pub fn with_policy(formatter: F, policy: P) -> Self {
+        EventFormatter::builder()
+            .visitor_size::<VISITOR_SIZE>()
+            .formatter(formatter)
+            .policy(policy)
+            .build()
+    }
+}
+
+/// Wraps a formatter into an [`EventFormatter`] fluently:
+///
+/// ```rust
+/// use tracing::Level;
+/// use tracing_rewrite::FormatEventExt;
+/// use tracing_subscriber::fmt;
+///
+/// let formatter = fmt::format()
+///     .compact()
+///     .with_rewrite::<16>(|metadata| (metadata.target() == "noisy").then_some(Level::TRACE));
+/// let subscriber = fmt::Subscriber::builder().event_format(formatter).finish();
+/// ```
+///
+/// It's implemented for every type, since `FormatEvent` is generic over the subscriber and the
+/// field formatter, that are only known once the formatter is handed to the subscriber: the result
+/// is a formatter only when `self` is.
+pub trait FormatEventExt: Sized {
+    /// Same as `EventFormatter::<VISITOR_SIZE, _, _>::new(self, check)`
+    fn with_rewrite<const VISITOR_SIZE: usize>(
+        self,
+        check: impl Check,
+    ) -> EventFormatter<VISITOR_SIZE, Self, impl Check> {
+        EventFormatter::new(self, check)
+    }
+}
+
+impl<F> FormatEventExt for F {}
+
+#[cfg(test)]
+mod tests {
+    use tracing::Level;
+    use tracing_subscriber::fmt::{self, format::DefaultFields};
+
+    use super::{FormatEventExt, RewritePolicy};
+    use crate::{tests::capture_with, Decision, EventFormatter};
+
+    #[test]
+    fn with_rewrite() {
+        let output = capture_with(
+            DefaultFields::new(),
+            fmt::format()
+                .compact()
+                .without_time()
+                .with_rewrite::<4>(|metadata| match *metadata.level() {
+                    Level::WARN => Decision::SetLevel(Level::ERROR),
+                    _ => Decision::Keep,
+                }),
+            || {
+                tracing::warn!(a = 1, "promoted");
+                tracing::info!("untouched");
+            },
+        );
+
+        assert_eq!(
+            output,
+            "ERROR tracing_rewrite::ext::tests: promoted a=1\n INFO tracing_rewrite::ext::tests: \
+             untouched\n"
+        );
+    }
+
+    // promotes the events of a target, testable without a subscriber
+    struct Promote(&'static str);
+
+    impl RewritePolicy for Promote {
+        type Output = Option<Level>;
+
+        fn decide(&self, metadata: &tracing::Metadata<'static>) -> Option<Level> {
+            (metadata.target() == self.0).then_some(Level::ERROR)
+        }
+    }
+
+    #[test]
+    fn policy() {
+        use crate::tests::TEST_META;
+
+        assert_eq!(
+            Promote(TEST_META.target()).decide(&TEST_META),
+            Some(Level::ERROR)
+        );
+        assert_eq!(Promote("other").decide(&TEST_META), None);
+
+        let output = capture_with(
+            DefaultFields::new(),
+            EventFormatter::<4, _, _>::with_policy(
+                fmt::format().compact().without_time(),
+                Promote("promoted"),
+            ),
+            || {
+                tracing::warn!(target: "promoted", "first");
+                tracing::warn!("second");
+            },
+        );
+
+        assert_eq!(
+            output,
+            "ERROR promoted: first\n WARN tracing_rewrite::ext::tests: second\n"
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Mutex, OnceLock},
+};
+
+use tracing::{callsite::Identifier, field::FieldSet, Metadata};
+
+use crate::THREAD_FIELD_NAME;
+
+type Names = &'static [&'static str];
+type Key = (Identifier, Names, Names);
+
+// field names must be 'static, so every extended list is leaked once per callsite and reused;
+// stacked formatters hand the same callsite with the fields appended by the outer ones,
+// so a callsite can have more than one list
+static INTERNED: OnceLock<Mutex<HashMap<Key, Vec<Names>>>> = OnceLock::new();
+
+// names of the static fields, leaked once per distinct list, so that rebuilding formatters
+// doesn't leak again, and equal lists share the entries of `INTERNED`
+static STATIC_NAMES: OnceLock<Mutex<HashSet<Names>>> = OnceLock::new();
+
+/// Returns a `FieldSet` with the same callsite of `metadata`, containing its fields followed by `extra`
+/// and by `statics`.
+///
+/// Fields recorded from the original event remain valid for the returned `FieldSet`,
+/// since they share the same callsite and the same indexes.
+pub fn extend(
+    metadata: &Metadata<'_>,
+    extra: &'static [&'static str],
+    statics: &'static [&'static str],
+) -> FieldSet {
+    let callsite = metadata.callsite();
+    let fields = metadata.fields();
+    let mut interned = INTERNED
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    let lists = interned
+        .entry((callsite.clone(), extra, statics))
+        .or_default();
+    let extends = |names: &&Names| {
+        names.len() == fields.len() + extra.len() + statics.len()
+            && fields
+                .iter()
+                .zip(names.iter())
+                .all(|(field, name)| field.name() == *name)
+    };
+    let names = match lists.iter().find(extends) {
+        Some(names) => *names,
+        None => {
+            let names = fields
+                .iter()
+                .map(|field| field.name())
+                .chain(extra.iter().copied())
+                .chain(statics.iter().copied())
+                .collect::<Vec<_>>();
+            let names: Names = Box::leak(names.into_boxed_slice());
+            lists.push(names);
+            names
+        }
+    };
+    FieldSet::new(names, callsite)
+}
+
+/// Constant fields appended to every rewritten event, see [`EventFormatter::with_static_fields`](crate::EventFormatter::with_static_fields),
+/// preceded by the name of the current thread when requested,
+/// see [`EventFormatter::with_thread_field`](crate::EventFormatter::with_thread_field)
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StaticFields {
+    names: &'static [&'static str],
+    values: &'static [(&'static str, &'static str)],
+    thread: bool,
+}
+
+impl StaticFields {
+    // `FieldSet` takes the names as a slice of their own, interned by content
+    fn new(values: &'static [(&'static str, &'static str)], thread: bool) -> Self {
+        let names = thread
+            .then_some(THREAD_FIELD_NAME)
+            .into_iter()
+            .chain(values.iter().map(|(name, _)| *name))
+            .collect::<Vec<_>>();
+        let mut interned = STATIC_NAMES
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let names = match interned.get(names.as_slice()) {
+            Some(names) => *names,
+            None => {
+                let names: Names = Box::leak(names.into_boxed_slice());
+                interned.insert(names);
+                names
+            }
+        };
+        StaticFields {
+            names,
+            values,
+            thread,
+        }
+    }
+
+    pub fn with_values(self, values: &'static [(&'static str, &'static str)]) -> Self {
+        StaticFields::new(values, self.thread)
+    }
+
+    pub fn with_thread(self) -> Self {
+        StaticFields::new(self.values, true)
+    }
+
+    pub fn names(&self) -> &'static [&'static str] {
+        self.names
+    }
+
+    /// whether the first name is `THREAD_FIELD_NAME`
+    pub fn thread(&self) -> bool {
+        self.thread
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &'static str> {
+        self.values.iter().map(|(_, value)| *value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StaticFields;
+    use crate::THREAD_FIELD_NAME;
+
+    #[test]
+    fn static_names_are_interned() {
+        let fields = &[("a", "1"), ("b", "2")];
+        let first = StaticFields::default().with_values(fields).with_thread();
+        let second = StaticFields::default().with_thread().with_values(fields);
+
+        assert_eq!(first.names(), [THREAD_FIELD_NAME, "a", "b"]);
+        assert!(std::ptr::eq(first.names(), second.names()));
+    }
+}
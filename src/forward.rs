@@ -0,0 +1,176 @@
+use std::cell::Cell;
+
+use tracing::{Dispatch, Event};
+
+thread_local! {
+    // set while an event is being forwarded, so that the sink can't trigger another forward
+    static FORWARDING: Cell<bool> = const { Cell::new(false) };
+}
+
+// resets `FORWARDING` even if the sink panics
+struct Forwarding;
+
+impl Drop for Forwarding {
+    fn drop(&mut self) {
+        FORWARDING.with(|forwarding| forwarding.set(false));
+    }
+}
+
+/// Dispatches the rewritten `event` to `sink` too, when there is one,
+/// see [`EventFormatterBuilder::forward_to`](crate::EventFormatterBuilder::forward_to).
+///
+/// Events rewritten while forwarding aren't forwarded again, however the formatters are wired.
+pub fn forward(sink: Option<&Dispatch>, event: &Event<'_>) {
+    let Some(sink) = sink else {
+        return;
+    };
+    if FORWARDING.with(|forwarding| forwarding.replace(true)) {
+        return;
+    }
+    let _forwarding = Forwarding;
+
+    if sink.enabled(event.metadata()) {
+        sink.event(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tracing::{
+        span::{Attributes, Id, Record},
+        Dispatch, Event, Level, Metadata, Subscriber,
+    };
+    use tracing_subscriber::fmt::{self, format::DefaultFields};
+
+    use crate::{
+        tests::{capture_with, Buffer},
+        EventFormatter, MetadataStrategy,
+    };
+
+    // subscriber formatting into `buffer`, rewriting every event to `ERROR`
+    // and forwarding them to `forward_to`, if any
+    fn sink(buffer: &Buffer, forward_to: Option<Dispatch>) -> Dispatch {
+        let builder = EventFormatter::builder()
+            .formatter(fmt::format().compact().without_time())
+            .check(|_| Some(Level::ERROR));
+        let builder = match forward_to {
+            Some(dispatch) => builder.forward_to(dispatch),
+            None => builder,
+        };
+        fmt::Subscriber::builder()
+            .with_ansi(false)
+            .with_writer(buffer.clone())
+            .event_format(builder.build())
+            .finish()
+            .into()
+    }
+
+    #[test]
+    fn forward_to() {
+        let alerts = Buffer::default();
+        let formatter = EventFormatter::builder()
+            .formatter(fmt::format().compact().without_time())
+            .check(|metadata| (*metadata.level() == Level::WARN).then_some(Level::ERROR))
+            .forward_to(sink(&alerts, None))
+            .build();
+
+        let output = capture_with(DefaultFields::new(), formatter, || {
+            tracing::warn!(a = 1, "rewritten");
+            tracing::warn!("fieldless");
+            tracing::info!("untouched");
+        });
+
+        // formatted normally, and only the rewritten events reach the sink
+        assert_eq!(
+            output,
+            "ERROR tracing_rewrite::forward::tests: rewritten a=1\nERROR \
+             tracing_rewrite::forward::tests: fieldless\n INFO \
+             tracing_rewrite::forward::tests: untouched\n"
+        );
+        assert_eq!(
+            alerts.contents(),
+            "ERROR tracing_rewrite::forward::tests: rewritten a=1\nERROR \
+             tracing_rewrite::forward::tests: fieldless\n"
+        );
+    }
+
+    #[test]
+    fn no_recursion() {
+        // the sink rewrites the event again and forwards it to a third subscriber
+        let inner = Buffer::default();
+        let further = Buffer::default();
+        let formatter = EventFormatter::builder()
+            .formatter(fmt::format().compact().without_time())
+            .check(|metadata| (*metadata.level() == Level::WARN).then_some(Level::ERROR))
+            .forward_to(sink(&inner, Some(sink(&further, None))))
+            .build();
+
+        let output = capture_with(DefaultFields::new(), formatter, || {
+            tracing::warn!("rewritten")
+        });
+
+        assert_eq!(output, "ERROR tracing_rewrite::forward::tests: rewritten\n");
+        assert_eq!(
+            inner.contents(),
+            "ERROR tracing_rewrite::forward::tests: rewritten\n"
+        );
+        // events are forwarded once, not along the whole chain
+        assert_eq!(further.contents(), "");
+    }
+
+    // keeps the metadata of every event, as a sink batching events for later would
+    #[derive(Clone, Default)]
+    struct Keeper(Arc<Mutex<Vec<&'static Metadata<'static>>>>);
+
+    impl Subscriber for Keeper {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, _: &Attributes<'_>) -> Id {
+            Id::from_u64(1)
+        }
+
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            self.0.lock().unwrap().push(event.metadata());
+        }
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn forwarded_metadata_is_never_freed() {
+        let keeper = Keeper::default();
+        // the default strategy without the leaking features
+        let formatter = EventFormatter::builder()
+            .formatter(fmt::format().compact().without_time())
+            .check(|metadata| (*metadata.level() == Level::WARN).then_some(Level::ERROR))
+            .metadata_strategy(MetadataStrategy::Free)
+            .forward_to(keeper.clone())
+            .build();
+
+        capture_with(DefaultFields::new(), formatter, || {
+            for i in 0..3 {
+                tracing::warn!(i, "rewritten");
+            }
+            tracing::warn!("fieldless");
+        });
+
+        // read once every event has been handled, Miri reports a use after free otherwise
+        let kept = keeper.0.lock().unwrap();
+        assert_eq!(kept.len(), 4);
+        for metadata in kept.iter() {
+            assert_eq!(*metadata.level(), Level::ERROR);
+            assert_eq!(metadata.target(), module_path!());
+        }
+        assert_eq!(kept[0].fields().field("i").map(|f| f.name()), Some("i"));
+    }
+}
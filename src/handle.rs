@@ -0,0 +1,67 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// Switches rewriting on and off at runtime, see [`EventFormatter::handle`](crate::EventFormatter::handle).
+///
+/// While disabled, events go straight to the inner formatter without calling the check.
+#[derive(Clone, Debug)]
+pub struct RewriteHandle(Arc<AtomicBool>);
+
+impl Default for RewriteHandle {
+    fn default() -> Self {
+        RewriteHandle(Arc::new(AtomicBool::new(true)))
+    }
+}
+
+impl RewriteHandle {
+    pub fn enable(&self) {
+        self.set(true)
+    }
+
+    /// Disables rewriting, e.g. to temporarily see the original levels during an incident
+    pub fn disable(&self) {
+        self.set(false)
+    }
+
+    pub fn set(&self, enabled: bool) {
+        self.0.store(enabled, Ordering::Relaxed)
+    }
+
+    // read on every event, ordering with other memory operations doesn't matter
+    pub fn is_enabled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::Level;
+    use tracing_subscriber::fmt::{self, format::DefaultFields};
+
+    use crate::{tests::capture_with, EventFormatter};
+
+    #[test]
+    fn toggle() {
+        let formatter =
+            EventFormatter::<10, _, _>::new(fmt::format().compact().without_time(), |_| {
+                Some(Level::WARN)
+            });
+        let handle = formatter.handle();
+
+        let output = capture_with(DefaultFields::new(), formatter, || {
+            tracing::error!("rewritten");
+            handle.disable();
+            tracing::error!("original");
+            handle.enable();
+            tracing::error!("rewritten again");
+        });
+
+        assert_eq!(
+            output,
+            " WARN tracing_rewrite::handle::tests: rewritten\nERROR tracing_rewrite::handle::tests: \
+             original\n WARN tracing_rewrite::handle::tests: rewritten again\n"
+        );
+    }
+}
@@ -0,0 +1,238 @@
+use std::any::TypeId;
+
+use tracing::{span, subscriber::Interest, Dispatch, Event, Metadata, Subscriber};
+use tracing_subscriber::{filter::LevelFilter, layer::Context, Layer};
+
+use crate::{visitor, with_rewritten, Decision, PooledText};
+
+/// Wraps a [`Layer`] so that it receives rewritten events, the same way
+/// [`EventFormatter`](crate::EventFormatter) does for the fmt formatting stage.
+///
+/// This works with any layer (OpenTelemetry, Bunyan, custom ones), but compared to
+/// [`EventFormatter`](crate::EventFormatter) it has some limitations:
+/// * only the wrapped layer sees the rewritten event, other layers of the subscriber see the original one
+/// * filtering happens on the original metadata: `register_callsite`, `enabled` and `event_enabled`
+///   of the wrapped layer, as well as per-layer filters, see the original level and target
+/// * the rewritten metadata doesn't belong to a registered callsite, so layers keeping
+///   per-callsite state keyed by `Metadata` address won't find it
+/// * spans are forwarded untouched, only events are rewritten
+///
+/// As for [`EventFormatter`](crate::EventFormatter), `VISITOR_SIZE` must be at least 1.
+pub struct RewriteLayer<const VISITOR_SIZE: usize, L, T> {
+    inner: L,
+    check: T,
+}
+
+impl<const VISITOR_SIZE: usize, L, T, R> RewriteLayer<VISITOR_SIZE, L, T>
+where
+    T: Fn(&Metadata<'static>) -> R + Send + Sync,
+    R: Into<Decision>,
+{
+    pub fn new(inner: L, check: T) -> Self {
+        const { assert!(VISITOR_SIZE > 0, "VISITOR_SIZE must be at least 1") };
+        Self { inner, check }
+    }
+}
+
+impl<const VISITOR_SIZE: usize, L, T, R, S> Layer<S> for RewriteLayer<VISITOR_SIZE, L, T>
+where
+    L: Layer<S>,
+    T: Fn(&Metadata<'static>) -> R + Send + Sync + 'static,
+    R: Into<Decision>,
+    S: Subscriber,
+{
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+
+        let decision: Decision = (self.check)(metadata).into();
+        if let Some(rewrite) = decision.into_rewrite() {
+            if rewrite.drop {
+                return;
+            }
+
+            let mut visitor =
+                visitor::Visitor::<VISITOR_SIZE, PooledText>::new(event.fields().count());
+            event.record(&mut visitor);
+
+            with_rewritten(
+                event,
+                &rewrite,
+                visitor,
+                None,
+                Default::default(),
+                Default::default(),
+                |event| self.inner.on_event(event, ctx),
+            )
+        } else {
+            self.inner.on_event(event, ctx)
+        }
+    }
+
+    fn on_register_dispatch(&self, subscriber: &Dispatch) {
+        self.inner.on_register_dispatch(subscriber)
+    }
+
+    fn on_layer(&mut self, subscriber: &mut S) {
+        self.inner.on_layer(subscriber)
+    }
+
+    fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
+        self.inner.register_callsite(metadata)
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>, ctx: Context<'_, S>) -> bool {
+        self.inner.enabled(metadata, ctx)
+    }
+
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        self.inner.on_new_span(attrs, id, ctx)
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        self.inner.max_level_hint()
+    }
+
+    fn on_record(&self, span: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        self.inner.on_record(span, values, ctx)
+    }
+
+    fn on_follows_from(&self, span: &span::Id, follows: &span::Id, ctx: Context<'_, S>) {
+        self.inner.on_follows_from(span, follows, ctx)
+    }
+
+    fn event_enabled(&self, event: &Event<'_>, ctx: Context<'_, S>) -> bool {
+        self.inner.event_enabled(event, ctx)
+    }
+
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        self.inner.on_enter(id, ctx)
+    }
+
+    fn on_exit(&self, id: &span::Id, ctx: Context<'_, S>) {
+        self.inner.on_exit(id, ctx)
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        self.inner.on_close(id, ctx)
+    }
+
+    fn on_id_change(&self, old: &span::Id, new: &span::Id, ctx: Context<'_, S>) {
+        self.inner.on_id_change(old, new, ctx)
+    }
+
+    #[doc(hidden)]
+    unsafe fn downcast_raw(&self, id: TypeId) -> Option<*const ()> {
+        if id == TypeId::of::<Self>() {
+            Some(self as *const _ as *const ())
+        } else {
+            self.inner.downcast_raw(id)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tracing::{Event, Level, Subscriber};
+    use tracing_subscriber::{fmt, layer::Context, prelude::*, registry::Registry, Layer};
+
+    use super::RewriteLayer;
+    use crate::{tests::Buffer, Rewrite};
+
+    // records the level of every received event
+    #[derive(Clone, Default)]
+    struct Levels(Arc<Mutex<Vec<Level>>>);
+
+    impl<S: Subscriber> Layer<S> for Levels {
+        fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+            self.0.lock().unwrap().push(*event.metadata().level());
+        }
+    }
+
+    #[test]
+    fn custom_layer() {
+        let levels = Levels::default();
+        let subscriber = Registry::default()
+            .with(RewriteLayer::<10, _, _>::new(levels.clone(), |metadata| {
+                (*metadata.level() == Level::ERROR).then_some(Level::WARN)
+            }));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::error!("rewritten");
+            tracing::info!("untouched");
+        });
+
+        assert_eq!(*levels.0.lock().unwrap(), [Level::WARN, Level::INFO]);
+    }
+
+    #[test]
+    fn fmt_layer() {
+        let buffer = Buffer::default();
+        let subscriber = Registry::default().with(RewriteLayer::<10, _, _>::new(
+            fmt::layer()
+                .compact()
+                .without_time()
+                .with_ansi(false)
+                .with_writer(buffer.clone()),
+            |_| Some(Level::WARN),
+        ));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("outer", id = 1);
+            let _guard = span.enter();
+            tracing::error!(a = 1, "test");
+        });
+
+        let output = buffer.contents();
+        assert!(
+            output.contains("WARN outer: tracing_rewrite::layer::tests: test a=1 id=1\n"),
+            "{output}"
+        );
+    }
+
+    #[test]
+    fn span_context() {
+        let capture = |rewrite: bool| {
+            let buffer = Buffer::default();
+            let layer = fmt::layer()
+                .without_time()
+                .with_ansi(false)
+                .with_writer(buffer.clone());
+            let subscriber = Registry::default()
+                .with(RewriteLayer::<10, _, _>::new(layer, move |_| {
+                    rewrite.then(Rewrite::default)
+                }));
+
+            tracing::subscriber::with_default(subscriber, || {
+                let outer = tracing::info_span!("outer", a = 1);
+                let _outer = outer.enter();
+                let inner = tracing::info_span!("inner", b = 2);
+                inner.in_scope(|| {
+                    tracing::info!("contextual");
+                    tracing::info!(parent: &outer, "explicit");
+                    tracing::info!(parent: None, "root");
+                });
+            });
+
+            let output = buffer.0.lock().unwrap().clone();
+            String::from_utf8(output).unwrap()
+        };
+
+        let original = capture(false);
+        assert!(
+            original
+                .contains(" outer{a=1}:inner{b=2}: tracing_rewrite::layer::tests: contextual\n"),
+            "{original}"
+        );
+        assert!(
+            original.contains(" outer{a=1}: tracing_rewrite::layer::tests: explicit\n"),
+            "{original}"
+        );
+        assert!(
+            original.contains(" INFO tracing_rewrite::layer::tests: root\n"),
+            "{original}"
+        );
+        assert_eq!(capture(true), original);
+    }
+}
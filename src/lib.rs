@@ -1,28 +1,507 @@
-use tracing::{field::FieldSet, Event, Level, Metadata, Subscriber};
-use tracing_core::Kind;
+use std::{
+    backtrace::{Backtrace, BacktraceStatus},
+    marker::PhantomData,
+    time::Duration,
+};
+
+use tracing::{
+    field::{Field, ValueSet},
+    Dispatch, Event, Level, Metadata, Subscriber, Value,
+};
 use tracing_subscriber::{
     fmt::{format::Writer, FmtContext, FormatEvent, FormatFields},
     registry::LookupSpan,
 };
 
-pub struct EventFormatter<const VISITOR_SIZE: usize, F, T> {
+use crate::{
+    fieldset::StaticFields,
+    throttle::{Admission, Sampling},
+};
+
+/// Name of the field added to rewritten events that have more fields than `VISITOR_SIZE`,
+/// its value is the number of fields that have been dropped
+pub const TRUNCATED_FIELD_NAME: &str = "fields_truncated";
+
+/// Name of the field added to events rewritten with [`Rewrite::original_level`],
+/// its value is the level of the event before the rewrite.
+///
+/// Like [`TRUNCATED_FIELD_NAME`], it is appended to the fields of the callsite rather than to the message,
+/// so structured formatters see it as a field of its own.
+pub const ORIGINAL_LEVEL_FIELD_NAME: &str = "original_level";
+
+/// Name of the field holding the backtrace captured when an event gets promoted,
+/// see `EventFormatterBuilder::backtrace`, available with the `backtrace` feature
+pub const BACKTRACE_FIELD_NAME: &str = "backtrace";
+
+/// Name of the field holding the name of the thread of rewritten events,
+/// see [`EventFormatter::with_thread_field`]
+pub const THREAD_FIELD_NAME: &str = "thread";
+
+/// Number of leaked metadata past which a warning is printed to stderr, see [`leaked_metadata_count`]
+pub const LEAKED_METADATA_WARNING: usize = 100_000;
+
+/// Field capacity of [`DefaultEventFormatter`], enough for most events
+pub const DEFAULT_VISITOR_SIZE: usize = 32;
+
+/// [`EventFormatter`] with a capacity of [`DEFAULT_VISITOR_SIZE`] fields,
+/// so that you can write `DefaultEventFormatter::new(formatter, check)`
+pub type DefaultEventFormatter<F, T> = EventFormatter<DEFAULT_VISITOR_SIZE, F, T>;
+
+/// Rewrites the events matched by `check` before handing them to `formatter`.
+///
+/// `check` returns a [`Decision`], or anything converting into one, like `Option<Level>`.
+///
+/// Filters run before formatting, on the original level: demoting an event below the level of
+/// an `EnvFilter` doesn't suppress it, use [`Rewrite::DROP`] for that.
+///
+/// `B` is the buffer string and `Debug` values are formatted into, [`PooledText`] by default,
+/// [`StackText`] avoids the heap entirely.
+///
+/// Rewritten events are recorded into the smallest of 4, 16 and 64 slots that holds them,
+/// falling back to `VISITOR_SIZE`, so that small events don't reserve the stack of the biggest ones:
+/// the rewrite path is compiled once per tier smaller than `VISITOR_SIZE`, growing the binary
+/// by up to 3 copies of it for every formatter type. Checks looking at the fields record them
+/// into `VISITOR_SIZE` slots before the check runs.
+///
+/// `VISITOR_SIZE` is the number of fields kept for every rewritten event and must be at least 1,
+/// building a formatter without room for any field doesn't compile:
+///
+/// ```compile_fail
+/// # use tracing::Level;
+/// # use tracing_subscriber::fmt;
+/// let formatter = tracing_rewrite::EventFormatter::<0, _, _>::new(fmt::format(), |_| Some(Level::WARN));
+/// ```
+pub struct EventFormatter<const VISITOR_SIZE: usize, F, T, B = PooledText> {
     formatter: F,
     check: T,
+    handle: RewriteHandle,
+    rate_limit: Option<RateLimit>,
+    sampling: Option<Sampling>,
+    transform: Option<Box<Transform>>,
+    on_rewrite: Option<Box<OnRewrite>>,
+    rewrite_spans: bool,
+    capture_fields: Option<&'static [&'static str]>,
+    backtrace: Option<Level>,
+    span_check: Option<Box<SpanCheck>>,
+    static_fields: StaticFields,
+    post_format: Option<Box<PostFormat>>,
+    tee: bool,
+    metadata_strategy: MetadataStrategy,
+    overflow: Overflow,
+    max_field_len: Option<usize>,
+    forward: Option<Dispatch>,
+    text: PhantomData<fn() -> B>,
 }
 
-impl<const VISITOR_SIZE: usize, F, T> EventFormatter<VISITOR_SIZE, F, T>
+impl<const VISITOR_SIZE: usize, F, T, B> EventFormatter<VISITOR_SIZE, F, T, B> {
+    /// Returns the handle switching rewriting on and off, take it before handing the formatter
+    /// to the subscriber
+    pub fn handle(&self) -> RewriteHandle {
+        self.handle.clone()
+    }
+
+    /// Appends the constant `fields` to every rewritten event, e.g. `&[("rewritten", "true")]`,
+    /// as fields of their own: structured formatters see them like any other string field.
+    ///
+    /// `Metadata` needs `'static` field names in a single slice, so the rewritten callsite gets a
+    /// `FieldSet` made of the original names followed by these ones, interned once per callsite.
+    /// The names of the static fields are interned too, so rebuilding formatters with the same
+    /// fields, e.g. on a configuration reload, doesn't leak them again.
+    /// Fields named like one of the event are appended anyway, so they show up twice.
+    pub fn with_static_fields(self, fields: &'static [(&'static str, &'static str)]) -> Self {
+        EventFormatter {
+            static_fields: self.static_fields.with_values(fields),
+            ..self
+        }
+    }
+
+    /// Appends the name of the current thread to every rewritten event, as a field named
+    /// [`THREAD_FIELD_NAME`], e.g. `thread="worker-3"`, or its `ThreadId` for unnamed threads.
+    ///
+    /// Formatters can print thread names already, this makes it a field of its own for
+    /// structured formatters like the JSON one. It comes before the fields of
+    /// [`EventFormatter::with_static_fields`].
+    pub fn with_thread_field(self) -> Self {
+        EventFormatter {
+            static_fields: self.static_fields.with_thread(),
+            ..self
+        }
+    }
+
+    // sampling and rate limiting decide whether a matching event actually gets rewritten
+    fn admit(&self, metadata: &Metadata<'_>) -> Admission {
+        if let Some(sampling) = &self.sampling {
+            if !sampling.sampled(metadata.callsite()) {
+                return Admission::Original;
+            }
+        }
+
+        match &self.rate_limit {
+            Some(limit) => limit.admit(metadata.callsite()),
+            None => Admission::Rewrite,
+        }
+    }
+
+    // span lifecycle events skip the check entirely when span rewriting is off
+    fn passthrough(&self, metadata: &Metadata<'_>) -> bool {
+        !self.handle.is_enabled() || (!self.rewrite_spans && metadata.is_span())
+    }
+
+    // span lifecycle events go to the span check, when there is one
+    fn span_check(&self, metadata: &Metadata<'_>) -> Option<&SpanCheck> {
+        self.span_check.as_deref().filter(|_| metadata.is_span())
+    }
+
+    fn notify(&self, metadata: &Metadata<'_>, rewrite: &Rewrite) {
+        if let Some(on_rewrite) = &self.on_rewrite {
+            let from = *metadata.level();
+            on_rewrite(metadata, from, rewrite.level.unwrap_or(from));
+        }
+    }
+
+    // captured only when the rewrite raises the level up to the threshold or above it, forced
+    // since `build` already dropped the threshold if the environment disables backtraces
+    fn backtrace(&self, metadata: &Metadata<'_>, rewrite: &Rewrite) -> Option<Backtrace> {
+        self.wants_backtrace(metadata, rewrite)
+            .then(Backtrace::force_capture)
+            .filter(|backtrace| backtrace.status() == BacktraceStatus::Captured)
+    }
+
+    fn wants_backtrace(&self, metadata: &Metadata<'_>, rewrite: &Rewrite) -> bool {
+        let (Some(threshold), Some(level)) = (self.backtrace, rewrite.level) else {
+            return false;
+        };
+        level < *metadata.level() && level <= threshold
+    }
+
+    // whether the rewritten event would lose some of its fields, injected ones included
+    fn overflows(&self, metadata: &Metadata<'_>, rewrite: &Rewrite) -> bool {
+        let fields = metadata.fields().len()
+            + usize::from(rewrite.original_level)
+            + usize::from(self.wants_backtrace(metadata, rewrite))
+            + self.static_fields.names().len();
+        fields > VISITOR_SIZE
+    }
+}
+
+/// Check receiving the recorded fields too, see [`EventFormatter::with_fields`]
+pub struct WithFields<T>(T);
+
+/// Check receiving the formatting context too, see [`EventFormatter::with_context`]
+pub struct WithContext<T>(T);
+
+/// Rewrites the text of string and `Debug` values, see [`EventFormatter::with_transform`]
+pub type Transform = dyn Fn(&Field, &str) -> Option<String> + Send + Sync;
+
+/// Decides the rewrite of span lifecycle events, see [`EventFormatterBuilder::check_spans`]
+pub type SpanCheck = dyn Fn(&Metadata<'static>) -> Decision + Send + Sync;
+
+/// Post-processes the formatted output of every rewritten event,
+/// see [`EventFormatterBuilder::post_format`]
+pub type PostFormat = dyn Fn(&mut String) + Send + Sync;
+
+/// Called with the original and the new level of every rewritten event,
+/// see [`EventFormatterBuilder::on_rewrite`]
+pub type OnRewrite = dyn Fn(&Metadata<'_>, Level, Level) + Send + Sync;
+
+/// What happens to rewritten events with more fields than `VISITOR_SIZE`,
+/// see [`EventFormatterBuilder::on_overflow`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// The fields that don't fit are dropped, and counted in a [`TRUNCATED_FIELD_NAME`] field
+    #[default]
+    Truncate,
+    /// The event isn't rewritten, it's formatted as it is, so that no field is lost
+    Passthrough,
+}
+
+impl<const VISITOR_SIZE: usize, F, T, R> EventFormatter<VISITOR_SIZE, F, T>
 where
-    T: Fn(&Metadata<'static>) -> Option<Level> + Send + Sync,
+    T: Fn(&Metadata<'static>) -> R + Send + Sync,
+    R: Into<Decision>,
 {
     pub fn new(formatter: F, check: T) -> Self {
-        Self { formatter, check }
+        EventFormatter::builder()
+            .visitor_size::<VISITOR_SIZE>()
+            .formatter(formatter)
+            .check(check)
+            .build()
+    }
+
+    /// Like [`EventFormatter::new`], but rewrites a synthetic event first, so that an
+    /// incompatibility with the linked `tracing` version shows up at init time
+    /// rather than when the first event gets rewritten
+    pub fn try_new(formatter: F, check: T) -> Result<Self, RewriteError> {
+        probe::probe()?;
+        Ok(EventFormatter::new(formatter, check))
+    }
+
+    /// Like [`EventFormatter::new`], with `transform` rewriting the values of the events matched by `check`,
+    /// e.g. to redact PII.
+    ///
+    /// `transform` receives the text of every value recorded as a string or through `Debug`,
+    /// returning `Some` replaces it, numbers, booleans and errors are left untouched.
+    ///
+    /// Values are otherwise formatted exactly as the wrapped formatter would format them,
+    /// newlines included: a transform can escape them for line-oriented outputs.
+    pub fn with_transform(
+        formatter: F,
+        check: T,
+        transform: impl Fn(&Field, &str) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        EventFormatter::builder()
+            .visitor_size::<VISITOR_SIZE>()
+            .formatter(formatter)
+            .check(check)
+            .transform(transform)
+            .build()
+    }
+
+    /// Like [`EventFormatter::new`], but `check` applies only until `window` has elapsed,
+    /// see [`time_windowed`]
+    pub fn time_windowed(
+        formatter: F,
+        check: T,
+        window: Duration,
+    ) -> EventFormatter<VISITOR_SIZE, F, impl Fn(&Metadata<'static>) -> Decision + Send + Sync>
+    {
+        EventFormatter::new(formatter, time_windowed(check, window))
     }
 }
 
-impl<const VISITOR_SIZE: usize, F, T, S, N> FormatEvent<S, N> for EventFormatter<VISITOR_SIZE, F, T>
+impl<const VISITOR_SIZE: usize, F, T, R> EventFormatter<VISITOR_SIZE, F, WithFields<T>>
 where
+    T: Fn(&Metadata<'static>, &FieldSnapshot<'_>) -> R + Send + Sync,
+    R: Into<Decision>,
+{
+    /// Like [`EventFormatter::new`], but `check` can also look at the values of the event,
+    /// e.g. to rewrite only events with a `status` field greater than 500.
+    ///
+    /// Fields are recorded before calling `check`, so they are recorded on every event.
+    pub fn with_fields(formatter: F, check: T) -> Self {
+        EventFormatter::builder()
+            .visitor_size::<VISITOR_SIZE>()
+            .formatter(formatter)
+            .check_with_fields(check)
+            .build()
+    }
+}
+
+impl<const VISITOR_SIZE: usize, F, T> EventFormatter<VISITOR_SIZE, F, WithContext<T>> {
+    /// Like [`EventFormatter::new`], but `check` can also look at the formatting context,
+    /// e.g. to rewrite the events emitted inside a span named `request`:
+    ///
+    /// ```rust
+    /// use tracing::Level;
+    /// use tracing_rewrite::EventFormatter;
+    /// use tracing_subscriber::fmt;
+    ///
+    /// let formatter = EventFormatter::<10, _, _>::with_context(fmt::format(), |_, ctx| {
+    ///     let span = ctx.lookup_current()?;
+    ///     (span.name() == "request").then_some(Level::INFO)
+    /// });
+    /// let subscriber = fmt::Subscriber::builder().event_format(formatter).finish();
+    /// ```
+    ///
+    /// The context gives access to the whole span tree of the event, extensions included:
+    /// `ctx.event_scope()` walks it from the span of the event up to the root, and
+    /// `span.extensions().get::<T>()` reads what other layers stored into every span.
+    pub fn with_context<S, N, R>(formatter: F, check: T) -> Self
+    where
+        T: Fn(&Metadata<'static>, &FmtContext<'_, S, N>) -> R + Send + Sync,
+        S: Subscriber + for<'a> LookupSpan<'a>,
+        N: for<'a> FormatFields<'a> + 'static,
+        R: Into<Decision>,
+    {
+        EventFormatter::builder()
+            .visitor_size::<VISITOR_SIZE>()
+            .formatter(formatter)
+            .check_with_context(check)
+            .build()
+    }
+}
+
+impl<const VISITOR_SIZE: usize, F, T, B: TextBuffer> EventFormatter<VISITOR_SIZE, F, T, B> {
+    // `recorded` holds the values already recorded for the check, if any
+    fn dispatch<S, N>(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        writer: Writer<'_>,
+        event: &Event<'_>,
+        decision: Decision,
+        recorded: Option<visitor::Visitor<VISITOR_SIZE, B>>,
+    ) -> std::fmt::Result
+    where
+        F: FormatEvent<S, N>,
+        S: Subscriber + for<'a> LookupSpan<'a>,
+        N: for<'a> FormatFields<'a> + 'static,
+    {
+        match decision {
+            Decision::Keep => self.formatter.format_event(ctx, writer, event),
+            Decision::Drop => Ok(()),
+            Decision::SetLevel(level) => self.rewrite(ctx, writer, event, level.into(), recorded),
+            Decision::Rewrite(patch) => self.rewrite(ctx, writer, event, patch, recorded),
+            Decision::Decorate(decoration) => {
+                marker::with_decoration(&decoration, writer, |writer| {
+                    self.formatter.format_event(ctx, writer, event)
+                })
+            }
+        }
+    }
+
+    // everything happening once the check has matched, `recorded` holds the values already
+    // recorded for the check, if any, otherwise the event is recorded only if actually rewritten
+    fn rewrite<S, N>(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+        rewrite: Rewrite,
+        recorded: Option<visitor::Visitor<VISITOR_SIZE, B>>,
+    ) -> std::fmt::Result
+    where
+        F: FormatEvent<S, N>,
+        S: Subscriber + for<'a> LookupSpan<'a>,
+        N: for<'a> FormatFields<'a> + 'static,
+    {
+        if rewrite.drop {
+            return Ok(());
+        }
+
+        let metadata = event.metadata();
+        if self.overflow == Overflow::Passthrough && self.overflows(metadata, &rewrite) {
+            return self.formatter.format_event(ctx, writer, event);
+        }
+        match self.admit(metadata) {
+            Admission::Rewrite => {}
+            Admission::Original => return self.formatter.format_event(ctx, writer, event),
+            Admission::Drop => return Ok(()),
+        }
+        if self.tee {
+            self.formatter.format_event(ctx, writer.by_ref(), event)?;
+        }
+        self.notify(metadata, &rewrite);
+        let backtrace = self.backtrace(metadata, &rewrite);
+
+        if let Some(visitor) = recorded {
+            return self.format_rewritten(ctx, writer, event, &rewrite, visitor, backtrace);
+        }
+
+        // the smallest tier holding every field, injected ones included, keeps the visitor
+        // from reserving the stack of `VISITOR_SIZE` fields for small events
+        let fields = event.fields().count()
+            + usize::from(rewrite.original_level)
+            + usize::from(backtrace.is_some())
+            + self.static_fields.names().len();
+        match fields {
+            0 => self.format_fieldless(ctx, writer, event, &rewrite),
+            1..=4 if VISITOR_SIZE > 4 => {
+                let visitor = visitor::record::<4, B>(event, self.max_field_len);
+                self.format_rewritten(ctx, writer, event, &rewrite, visitor, backtrace)
+            }
+            5..=16 if VISITOR_SIZE > 16 => {
+                let visitor = visitor::record::<16, B>(event, self.max_field_len);
+                self.format_rewritten(ctx, writer, event, &rewrite, visitor, backtrace)
+            }
+            17..=64 if VISITOR_SIZE > 64 => {
+                let visitor = visitor::record::<64, B>(event, self.max_field_len);
+                self.format_rewritten(ctx, writer, event, &rewrite, visitor, backtrace)
+            }
+            _ => {
+                let visitor = visitor::record::<VISITOR_SIZE, B>(event, self.max_field_len);
+                self.format_rewritten(ctx, writer, event, &rewrite, visitor, backtrace)
+            }
+        }
+    }
+
+    // kept out of line, so that the stack of every tier isn't reserved by the caller at once
+    #[inline(never)]
+    fn format_rewritten<const SIZE: usize, S, N>(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        writer: Writer<'_>,
+        event: &Event<'_>,
+        rewrite: &Rewrite,
+        mut visitor: visitor::Visitor<SIZE, B>,
+        backtrace: Option<Backtrace>,
+    ) -> std::fmt::Result
+    where
+        F: FormatEvent<S, N>,
+        S: Subscriber + for<'a> LookupSpan<'a>,
+        N: for<'a> FormatFields<'a> + 'static,
+    {
+        if let Some(transform) = &self.transform {
+            visitor.transform(transform);
+        }
+
+        self.with_markers(writer, event, rewrite, |writer| {
+            let backtrace = backtrace.as_ref();
+            with_rewritten(
+                event,
+                rewrite,
+                visitor,
+                backtrace,
+                self.static_fields,
+                self.metadata_strategy,
+                |event| {
+                    forward::forward(self.forward.as_ref(), event);
+                    self.formatter.format_event(ctx, writer, event)
+                },
+            )
+        })
+    }
+
+    // events without fields, nor fields to inject, don't need a visitor
+    fn format_fieldless<S, N>(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        writer: Writer<'_>,
+        event: &Event<'_>,
+        rewrite: &Rewrite,
+    ) -> std::fmt::Result
+    where
+        F: FormatEvent<S, N>,
+        S: Subscriber + for<'a> LookupSpan<'a>,
+        N: for<'a> FormatFields<'a> + 'static,
+    {
+        self.with_markers(writer, event, rewrite, |writer| {
+            let rewritten =
+                self.metadata_strategy
+                    .rewritten(event.metadata(), rewrite, Default::default());
+            let metadata = rewritten.get();
+            if !diagnostics::fields_valid(event.metadata(), metadata) {
+                return self.formatter.format_event(ctx, writer, event);
+            }
+            let values: [(&Field, Option<&dyn Value>); 0] = [];
+            let valueset = metadata.fields().value_set(&values);
+            let event = new_event(event, metadata, &valueset);
+            forward::forward(self.forward.as_ref(), &event);
+            self.formatter.format_event(ctx, writer, &event)
+        })
+    }
+
+    // everything written around the rewritten event: post format hook, markers and traces
+    fn with_markers(
+        &self,
+        writer: Writer<'_>,
+        event: &Event<'_>,
+        rewrite: &Rewrite,
+        f: impl FnOnce(Writer<'_>) -> std::fmt::Result,
+    ) -> std::fmt::Result {
+        marker::with_post_format(self.post_format.as_deref(), writer, |writer| {
+            marker::with_markers(rewrite, writer, |writer| {
+                marker::with_trace(event.metadata(), rewrite, writer, f)
+            })
+        })
+    }
+}
+
+impl<const VISITOR_SIZE: usize, F, T, B, S, N> FormatEvent<S, N>
+    for EventFormatter<VISITOR_SIZE, F, T, B>
+where
+    B: TextBuffer,
     F: FormatEvent<S, N>,
-    T: Fn(&Metadata<'static>) -> Option<Level> + Send + Sync,
+    T: RewritePolicy,
     S: Subscriber + for<'a> LookupSpan<'a>,
     N: for<'a> FormatFields<'a> + 'static,
 {
@@ -33,155 +512,428 @@ where
         event: &Event<'_>,
     ) -> std::fmt::Result {
         let metadata = event.metadata();
+        if self.passthrough(metadata) {
+            return self.formatter.format_event(ctx, writer, event);
+        }
+        if let Some(check) = self.span_check(metadata) {
+            return self.dispatch(ctx, writer, event, check(metadata), None);
+        }
+        if let Some(decision) = policy::thread_policy(metadata) {
+            return self.dispatch(ctx, writer, event, decision, None);
+        }
 
-        if let Some(level) = (self.check)(metadata) {
-            let kind = if metadata.is_event() {
-                Kind::EVENT
-            } else if metadata.is_span() {
-                Kind::SPAN
-            } else {
-                unreachable!()
-            };
+        let decision = self.check.decide(metadata).into();
+        self.dispatch(ctx, writer, event, decision, None)
+    }
+}
 
-            let fields = metadata.fields();
-            // Safety: at the moment of writing this code, FieldSet is made like
-            // ```rust
-            // pub struct FieldSet {
-            //   names: &'static [&'static str],
-            //   callsite: callsite::Identifier,
-            // }
-            // ```
-            // and Identifier is make like
-            // ```rust
-            // #[derive(Clone)]
-            // pub struct Identifier(
-            //   #[doc(hidden)]
-            //   pub &'static dyn Callsite,
-            // );
-            // ```
-            // that means we can copy the static references without causing any UB
-            let cloned = unsafe { std::mem::transmute_copy::<FieldSet, FieldSet>(fields) };
-
-            // here we are leaking memory, but should be mainly references
-            let metadata = Box::leak::<'static>(Box::new(Metadata::new(
-                metadata.name(),
-                metadata.target(),
-                level,
-                metadata.file(),
-                metadata.line(),
-                metadata.module_path(),
-                cloned,
-                kind,
-            )));
-
-            let mut visitor = visitor::Visitor::<VISITOR_SIZE>::new();
-            event.record(&mut visitor);
-            let values = visitor.get_values();
-            let valueset = fields.value_set(&values);
-            let event = if let Some(parent) = event.parent() {
-                Event::new_child_of(parent, metadata, &valueset)
-            } else {
-                Event::new(metadata, &valueset)
-            };
-            let res = self.formatter.format_event(ctx, writer, &event);
+impl<const VISITOR_SIZE: usize, F, T, B, R, S, N> FormatEvent<S, N>
+    for EventFormatter<VISITOR_SIZE, F, WithFields<T>, B>
+where
+    B: TextBuffer,
+    F: FormatEvent<S, N>,
+    T: Fn(&Metadata<'static>, &FieldSnapshot<'_>) -> R + Send + Sync,
+    R: Into<Decision>,
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> std::fmt::Result {
+        let metadata = event.metadata();
+        if self.passthrough(metadata) {
+            return self.formatter.format_event(ctx, writer, event);
+        }
+        if let Some(check) = self.span_check(metadata) {
+            return self.dispatch(ctx, writer, event, check(metadata), None);
+        }
+        if let Some(decision) = policy::thread_policy(metadata) {
+            return self.dispatch(ctx, writer, event, decision, None);
+        }
+
+        // fields are visited only once, before the check, unless the check needs only some of them
+        let mut visitor = match self.capture_fields {
+            Some(names) => visitor::Visitor::only(names),
+            None => visitor::Visitor::new(event.fields().count()),
+        }
+        .max_len(self.max_field_len);
+        event.record(&mut visitor);
 
-            // here we're freeing the leaked memory
-            // Miri tells us we're doing an invalid operation, because metadata is borrowed for 'static
-            // and we don't have any guarantee the implementor of the trait is keeping references to it
-            // that is possible, but unlikely.
-            // If you're experiencing UB, please enable `i_really_want_memory_leak`  feature
-            #[cfg(not(feature = "i_really_want_memory_leak"))]
-            drop(unsafe { Box::from_raw(metadata as *const Metadata as *mut Metadata) });
+        let decision = (self.check.0)(metadata, &visitor.snapshot()).into();
+        // a partial recording isn't enough, the rewritten event needs every field
+        let recorded = self.capture_fields.is_none().then_some(visitor);
+        self.dispatch(ctx, writer, event, decision, recorded)
+    }
+}
 
-            res
-        } else {
-            self.formatter.format_event(ctx, writer, event)
+impl<const VISITOR_SIZE: usize, F, T, B, R, S, N> FormatEvent<S, N>
+    for EventFormatter<VISITOR_SIZE, F, WithContext<T>, B>
+where
+    B: TextBuffer,
+    F: FormatEvent<S, N>,
+    T: Fn(&Metadata<'static>, &FmtContext<'_, S, N>) -> R + Send + Sync,
+    R: Into<Decision>,
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> std::fmt::Result {
+        let metadata = event.metadata();
+        if self.passthrough(metadata) {
+            return self.formatter.format_event(ctx, writer, event);
+        }
+        if let Some(check) = self.span_check(metadata) {
+            return self.dispatch(ctx, writer, event, check(metadata), None);
         }
+        if let Some(decision) = policy::thread_policy(metadata) {
+            return self.dispatch(ctx, writer, event, decision, None);
+        }
+
+        let decision = (self.check.0)(metadata, ctx).into();
+        self.dispatch(ctx, writer, event, decision, None)
     }
 }
 
-mod visitor {
-    use std::fmt::Debug;
+// builds the rewritten event out of the values recorded by `visitor` and hands it to `f`.
+//
+// Even rewrites changing only the level go through the visitor: `Event` doesn't expose its `ValueSet`
+// and `Value` is sealed, so the original values can't be forwarded without being recorded first.
+fn with_rewritten<const VISITOR_SIZE: usize, B: TextBuffer, R>(
+    event: &Event<'_>,
+    rewrite: &Rewrite,
+    mut visitor: visitor::Visitor<VISITOR_SIZE, B>,
+    backtrace: Option<&Backtrace>,
+    static_fields: StaticFields,
+    metadata_strategy: MetadataStrategy,
+    f: impl FnOnce(&Event<'_>) -> R,
+) -> R {
+    visitor.reserve(
+        usize::from(rewrite.original_level)
+            + usize::from(backtrace.is_some())
+            + static_fields.names().len(),
+    );
+    let injected = metadata::Injected {
+        truncated: visitor.dropped() > 0,
+        original_level: rewrite.original_level,
+        backtrace: backtrace.is_some(),
+        statics: static_fields.names(),
+    };
+    // freed once the event has been handled, when going out of scope
+    let rewritten = metadata_strategy.rewritten(event.metadata(), rewrite, injected);
+    let metadata = rewritten.get();
+    if !diagnostics::fields_valid(event.metadata(), metadata) {
+        return f(event);
+    }
 
-    use tracing::{field::Visit, Level, Metadata, Value};
-    use tracing_core::{metadata, Callsite, Field, Interest, Kind};
+    // injected fields follow the ones of the original callsite
+    let mut fields = metadata
+        .fields()
+        .iter()
+        .skip(event.metadata().fields().len());
+    if injected.truncated {
+        visitor.record_dropped(fields.next().unwrap());
+    }
+    if injected.original_level {
+        visitor.record_str(fields.next().unwrap(), event.metadata().level().as_str());
+    }
+    if let Some(backtrace) = backtrace {
+        visitor.record_str(fields.next().unwrap(), &backtrace.to_string());
+    }
+    if static_fields.thread() {
+        let thread = std::thread::current();
+        let field = fields.next().unwrap();
+        match thread.name() {
+            Some(name) => visitor.record_str(field, name),
+            None => visitor.record_str(field, &format!("{:?}", thread.id())),
+        }
+    }
+    for (field, value) in fields.zip(static_fields.values()) {
+        visitor.record_str(field, value);
+    }
 
-    const FAKE_FIELD_NAME: &str = "foo";
+    let recorded = visitor.recorded();
+    visitor.with_value_set(metadata.fields(), |valueset| {
+        if !diagnostics::consistent(valueset, recorded, metadata) {
+            return f(event);
+        }
+        f(&new_event(event, metadata, valueset))
+    })
+}
 
-    // tracing automatically filters out fields with a different call site
-    struct FakeCallSite();
-    static FAKE_CALLSITE: FakeCallSite = FakeCallSite();
-    static FAKE_META: Metadata<'static> = metadata! {
-        name: "",
-        target: module_path!(),
-        level: Level::INFO,
-        fields: &[FAKE_FIELD_NAME],
-        callsite: &FAKE_CALLSITE,
-        kind: Kind::SPAN,
+fn new_event<'a>(
+    original: &Event<'_>,
+    metadata: &'static Metadata<'static>,
+    valueset: &'a ValueSet<'a>,
+) -> Event<'a> {
+    match original.parent() {
+        Some(parent) => Event::new_child_of(parent.clone(), metadata, valueset),
+        // explicit root events must not pick up the current span
+        None if original.is_root() => Event::new_child_of(None, metadata, valueset),
+        None => Event::new(metadata, valueset),
+    }
+}
+
+mod boxed;
+mod builder;
+mod chain;
+mod decision;
+mod diagnostics;
+mod dynamic;
+mod escalate;
+mod ext;
+mod fieldset;
+mod forward;
+mod handle;
+mod layer;
+mod marker;
+mod metadata;
+mod policy;
+mod predicate;
+mod probe;
+mod remap;
+mod rewrite;
+mod shared;
+mod stateful;
+#[cfg(all(tracing_unstable, feature = "valuable"))]
+mod structured;
+#[cfg(feature = "test-util")]
+mod test_util;
+mod text;
+mod threshold;
+mod throttle;
+mod visitor;
+mod window;
+
+pub use boxed::{BoxedEventFormatter, BoxedFormatter};
+pub use builder::EventFormatterBuilder;
+pub use chain::chain;
+pub use decision::{Decision, Decoration, MetadataPatch};
+pub use dynamic::DynEventFormatter;
+pub use escalate::{escalate_after, escalate_after_within};
+pub use ext::{Check, FormatEventExt, RewritePolicy};
+pub use handle::RewriteHandle;
+pub use layer::RewriteLayer;
+pub use metadata::{leaked_metadata_count, MetadataStrategy};
+pub use policy::{clear_thread_policy, set_thread_policy, ThreadPolicy};
+pub use predicate::{
+    at_level, in_file, target_prefix, And, AtLevel, InFile, Or, Predicate, TargetPrefix,
+};
+pub use probe::RewriteError;
+#[cfg(feature = "regex")]
+pub use remap::remap_regex;
+pub use remap::{clamp_max, remap, RemapError, RemapRules, ANY_LEVEL};
+pub use rewrite::{MetadataKind, Rewrite};
+pub use shared::SharedEventFormatter;
+pub use stateful::stateful;
+pub use text::{PooledText, StackText, TextBuffer};
+pub use threshold::field_ge;
+pub use throttle::RateLimit;
+pub use visitor::{FieldSnapshot, FieldValue};
+pub use window::time_windowed;
+
+#[doc(hidden)]
+pub mod __private {
+    pub use tracing::Level;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    use tracing::{field::Field, Event, Level, Metadata, Subscriber};
+    use tracing_subscriber::{
+        fmt::{
+            self,
+            format::{FmtSpan, JsonFields, Writer},
+            FmtContext, FormatEvent, FormatFields, MakeWriter,
+        },
+        registry::{LookupSpan, Registry},
+        util::{SubscriberInitExt, TryInitError},
+        EnvFilter,
     };
 
-    impl Callsite for FakeCallSite {
-        fn set_interest(&self, _: Interest) {
-            unimplemented!()
+    // tests depending on wall-clock time, sleeping or measuring elapsed time, are
+    // `#[cfg_attr(miri, ignore)]` across the crate: Miri doesn't model it realistically
+
+    #[derive(Clone, Default)]
+    pub(crate) struct Buffer(pub(crate) Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
         }
 
-        fn metadata(&self) -> &Metadata<'_> {
-            &FAKE_META
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
         }
     }
 
-    pub struct Visitor<const N: usize> {
-        index: usize,
-        // TODO: avoid allocating with String
-        values: [(Field, Option<String>); N],
+    impl<'a> MakeWriter<'a> for Buffer {
+        type Writer = Self;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
     }
 
-    impl<const N: usize> Visitor<N> {
-        pub fn new() -> Self {
-            Visitor {
-                index: 0,
-                values: [(); N].map(|_| (FAKE_META.fields().field(FAKE_FIELD_NAME).unwrap(), None)),
-            }
+    impl Buffer {
+        /// Everything written so far, see [`untraced`]
+        pub(crate) fn contents(&self) -> String {
+            untraced(String::from_utf8(self.0.lock().unwrap().clone()).unwrap())
         }
+    }
 
-        pub fn get_values(&self) -> [(&Field, Option<&dyn Value>); N] {
-            let mut index = 0;
-            [(); N].map(|_| {
-                let val = (
-                    &self.values[index].0,
-                    self.values[index].1.as_ref().map(|s| s as &dyn Value),
-                );
-                index += 1;
-                val
-            })
+    // callsite for tests that need metadata without emitting events
+    pub(crate) struct TestCallsite;
+    pub(crate) static TEST_CALLSITE: TestCallsite = TestCallsite;
+    pub(crate) static TEST_META: Metadata<'static> = tracing_core::metadata! {
+        name: "test",
+        target: module_path!(),
+        level: tracing::Level::INFO,
+        fields: &["first", "second"],
+        callsite: &TEST_CALLSITE,
+        kind: tracing_core::Kind::EVENT,
+    };
+
+    impl tracing_core::Callsite for TestCallsite {
+        fn set_interest(&self, _: tracing_core::Interest) {}
+
+        fn metadata(&self) -> &Metadata<'_> {
+            &TEST_META
         }
     }
 
-    impl<const N: usize> Visit for Visitor<N> {
-        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
-            // Safety: same assumptions as before, becuase Field is like
-            // ```rust
-            // #[derive(Debug)]
-            // pub struct Field {
-            //     i: usize,
-            //     fields: FieldSet,
-            // }
-            // ```
-            let cloned = unsafe { std::mem::transmute_copy::<Field, Field>(field) };
-            self.values[self.index] = (cloned, Some(format!("{value:?}")));
-            self.index += 1;
+    // writes down the metadata of every event, to check what the inner formatter receives
+    pub(crate) struct MetadataFormatter;
+
+    impl<S, N> FormatEvent<S, N> for MetadataFormatter
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+        N: for<'a> FormatFields<'a> + 'static,
+    {
+        fn format_event(
+            &self,
+            _: &FmtContext<'_, S, N>,
+            mut writer: Writer<'_>,
+            event: &Event<'_>,
+        ) -> std::fmt::Result {
+            let metadata = event.metadata();
+            writeln!(
+                writer,
+                "{} {} {} {:?} {:?} {:?}",
+                metadata.name(),
+                metadata.target(),
+                metadata.level(),
+                metadata.file(),
+                metadata.line(),
+                metadata.module_path(),
+            )
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use tracing::{Level, Metadata};
-    use tracing_subscriber::{
-        fmt,
-        util::{SubscriberInitExt, TryInitError},
-        EnvFilter,
-    };
+    // runs `f` with a scoped subscriber and returns everything it wrote
+    pub(crate) fn capture<N, F, R>(
+        fields: N,
+        formatter: F,
+        check: impl Fn(&Metadata<'static>) -> R + Send + Sync + 'static,
+        f: impl FnOnce(),
+    ) -> String
+    where
+        N: for<'a> FormatFields<'a> + Send + Sync + 'static,
+        F: FormatEvent<Registry, N> + Send + Sync + 'static,
+        R: Into<super::Decision>,
+    {
+        capture_with(
+            fields,
+            super::EventFormatter::<10, _, _>::new(formatter, check),
+            f,
+        )
+    }
+
+    pub(crate) fn capture_with<N, E>(fields: N, event_format: E, f: impl FnOnce()) -> String
+    where
+        N: for<'a> FormatFields<'a> + Send + Sync + 'static,
+        E: FormatEvent<Registry, N> + Send + Sync + 'static,
+    {
+        capture_spans(fields, event_format, FmtSpan::NONE, f)
+    }
+
+    /// Like [`capture_with`], formatting the `spans` lifecycle events too
+    pub(crate) fn capture_spans<N, E>(
+        fields: N,
+        event_format: E,
+        spans: FmtSpan,
+        f: impl FnOnce(),
+    ) -> String
+    where
+        N: for<'a> FormatFields<'a> + Send + Sync + 'static,
+        E: FormatEvent<Registry, N> + Send + Sync + 'static,
+    {
+        let buffer = Buffer::default();
+        let subscriber = fmt::Subscriber::builder()
+            .with_max_level(Level::TRACE)
+            .with_ansi(false)
+            .with_span_events(spans)
+            .with_writer(buffer.clone())
+            .fmt_fields(fields)
+            .event_format(event_format)
+            .finish();
+        tracing::subscriber::with_default(subscriber, f);
+        buffer.contents()
+    }
+
+    /// Drops the ` from=.. to=.. callsite=".."` annotations written with `trace_rewrites`,
+    /// so that expected outputs are the same with and without the feature
+    #[cfg(feature = "trace_rewrites")]
+    pub(crate) fn untraced(output: String) -> String {
+        let mut untraced = String::with_capacity(output.len());
+        let mut rest = output.as_str();
+        while let Some(start) = rest.find(" from=") {
+            let annotation = &rest[start..];
+            let end = annotation
+                .find("callsite=\"")
+                .and_then(|callsite| {
+                    let value = callsite + "callsite=\"".len();
+                    Some(value + annotation[value..].find('"')? + 1)
+                })
+                .filter(|end| !annotation[..*end].contains('\n'));
+            match end {
+                Some(end) => {
+                    untraced.push_str(&rest[..start]);
+                    rest = &annotation[end..];
+                }
+                None => {
+                    untraced.push_str(&rest[..start + 1]);
+                    rest = &annotation[1..];
+                }
+            }
+        }
+        untraced.push_str(rest);
+        untraced
+    }
+
+    #[cfg(not(feature = "trace_rewrites"))]
+    pub(crate) fn untraced(output: String) -> String {
+        output
+    }
+
+    /// The level of every line of `output`, for formatters writing it first
+    pub(crate) fn levels(output: &str) -> Vec<&str> {
+        output
+            .lines()
+            .map(|line| line.split_whitespace().next().unwrap())
+            .collect()
+    }
 
     fn init_tracing(
         check: impl Fn(&Metadata<'static>) -> Option<Level> + Send + Sync + 'static,
@@ -206,6 +958,174 @@ mod tests {
             .try_init()
     }
 
+    #[test]
+    fn stacked() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let buffer = Buffer::default();
+        // the outer formatter renames the `db` target the first time only, the inner one
+        // promotes whatever comes out of the outer one, reading the rewritten metadata:
+        // the same callsite reaches it both rewritten and untouched
+        let first = AtomicBool::new(true);
+        let subscriber = fmt::Subscriber::builder()
+            .with_max_level(Level::TRACE)
+            .with_ansi(false)
+            .with_writer(buffer.clone())
+            .event_format(fmt::format().compact().without_time())
+            .map_event_format(|formatter| {
+                super::EventFormatter::<10, _, _>::new(formatter, |metadata| {
+                    match (metadata.target(), *metadata.level()) {
+                        (_, Level::WARN) => Some(super::Rewrite {
+                            level: Some(Level::ERROR),
+                            ..Default::default()
+                        }),
+                        (_, Level::DEBUG) => Some(Level::INFO.into()),
+                        _ => None,
+                    }
+                })
+            })
+            .map_event_format(move |formatter| {
+                super::EventFormatter::<10, _, _>::new(formatter, move |metadata| {
+                    (metadata.target() == "db" && first.swap(false, Ordering::Relaxed)).then_some(
+                        super::Rewrite {
+                            target: Some("database"),
+                            level: Some(Level::WARN),
+                            original_level: true,
+                            ..Default::default()
+                        },
+                    )
+                })
+            })
+            .finish();
+
+        tracing::subscriber::with_default(subscriber, || {
+            for i in 0..2 {
+                tracing::warn!(target: "db", i, "query");
+            }
+            tracing::debug!(a = 1, "inner only");
+        });
+
+        let output = buffer.contents();
+        assert_eq!(
+            output,
+            format!(
+                "ERROR database: query i=0 {}=\"WARN\"\nERROR db: query \
+                 i=1\n INFO tracing_rewrite::tests: inner only a=1\n",
+                super::ORIGINAL_LEVEL_FIELD_NAME
+            )
+        );
+    }
+
+    #[test]
+    fn check_with_context() {
+        let output = capture_with(
+            fmt::format::DefaultFields::new(),
+            super::DefaultEventFormatter::with_context(
+                fmt::format().compact().without_time(),
+                |_, ctx| {
+                    let span = ctx.lookup_current()?;
+                    (span.name() == "request").then_some(Level::WARN)
+                },
+            ),
+            || {
+                tracing::info!("outside");
+                tracing::info_span!("request").in_scope(|| {
+                    tracing::info!("inside");
+                    tracing::info_span!("nested").in_scope(|| tracing::info!("nested"));
+                });
+            },
+        );
+
+        assert_eq!(
+            output,
+            " INFO tracing_rewrite::tests: outside\n WARN request: tracing_rewrite::tests: inside\n \
+             INFO request:nested: tracing_rewrite::tests: nested\n"
+        );
+    }
+
+    // extension stored by `Tagger` into the spans with a `critical` field
+    struct Critical;
+
+    struct Tagger;
+
+    impl<S: Subscriber + for<'a> LookupSpan<'a>> tracing_subscriber::Layer<S> for Tagger {
+        fn on_new_span(
+            &self,
+            attrs: &tracing::span::Attributes<'_>,
+            id: &tracing::span::Id,
+            ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            if attrs.metadata().fields().field("critical").is_some() {
+                ctx.span(id).unwrap().extensions_mut().insert(Critical);
+            }
+        }
+    }
+
+    #[test]
+    fn check_with_extensions() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let buffer = Buffer::default();
+        let formatter = super::DefaultEventFormatter::with_context(
+            fmt::format().compact().without_time(),
+            |_, ctx| {
+                // any span of the event, not only the innermost one
+                let critical = ctx
+                    .event_scope()?
+                    .any(|span| span.extensions().get::<Critical>().is_some());
+                critical.then_some(Level::ERROR)
+            },
+        );
+        let subscriber = Registry::default().with(Tagger).with(
+            fmt::layer()
+                .with_ansi(false)
+                .with_writer(buffer.clone())
+                .event_format(formatter),
+        );
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("outside");
+            tracing::info_span!("payment", critical = true).in_scope(|| {
+                tracing::info_span!("nested").in_scope(|| tracing::info!("inside"));
+            });
+        });
+
+        let output = buffer.contents();
+        assert_eq!(
+            output,
+            " INFO tracing_rewrite::tests: outside\nERROR payment:nested: tracing_rewrite::tests: \
+             inside critical=true\n"
+        );
+    }
+
+    #[test]
+    fn demoted_events_are_not_filtered() {
+        let buffer = Buffer::default();
+        let subscriber = fmt::Subscriber::builder()
+            .with_env_filter(EnvFilter::new("warn"))
+            .with_ansi(false)
+            .with_writer(buffer.clone())
+            .event_format(super::DefaultEventFormatter::new(
+                fmt::format().compact().without_time(),
+                |metadata| match *metadata.level() {
+                    Level::WARN => Some(super::Rewrite::from(Level::TRACE)),
+                    Level::ERROR => Some(super::Rewrite::DROP),
+                    _ => None,
+                },
+            ))
+            .finish();
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::warn!("demoted");
+            tracing::info!("filtered");
+            tracing::error!("dropped");
+        });
+
+        // the filter runs on the original level, before the check
+        let output = buffer.contents();
+        assert_eq!(output, "TRACE tracing_rewrite::tests: demoted\n");
+    }
+
     #[test]
     fn miri_tracing() {
         init_tracing(|metadata| {
@@ -217,4 +1137,929 @@ mod tests {
 
         tracing::error!("test");
     }
+
+    #[test]
+    fn integers_keep_their_type() {
+        let output = capture(
+            JsonFields::new(),
+            fmt::format().json().without_time(),
+            |_| Some(Level::WARN),
+            || tracing::error!(signed = -42i64, unsigned = 42u64, "test"),
+        );
+
+        assert!(output.contains(r#""level":"WARN""#), "{output}");
+        assert!(output.contains(r#""signed":-42"#), "{output}");
+        assert!(output.contains(r#""unsigned":42"#), "{output}");
+    }
+
+    #[test]
+    fn wide_integers_keep_their_type() {
+        let output = capture(
+            fmt::format::DefaultFields::new(),
+            fmt::format().compact().without_time(),
+            |_| Some(Level::WARN),
+            || tracing::error!(signed = i128::MIN, unsigned = u128::MAX, "test"),
+        );
+
+        assert!(
+            output.contains(&format!("signed={}", i128::MIN)),
+            "{output}"
+        );
+        assert!(
+            output.contains(&format!("unsigned={}", u128::MAX)),
+            "{output}"
+        );
+    }
+
+    #[test]
+    fn strings_are_not_quoted_twice() {
+        let output = capture(
+            fmt::format::DefaultFields::new(),
+            fmt::format().compact().without_time(),
+            |_| Some(Level::WARN),
+            || tracing::error!(message = "hello", name = "world", list = ?[1, 2]),
+        );
+
+        assert!(
+            output.contains(r#"WARN tracing_rewrite::tests: hello name="world" list=[1, 2]"#),
+            "{output}"
+        );
+
+        let output = capture(
+            JsonFields::new(),
+            fmt::format().json().without_time(),
+            |_| Some(Level::WARN),
+            || tracing::error!(message = "hello", name = "world"),
+        );
+
+        assert!(output.contains(r#""message":"hello""#), "{output}");
+        assert!(output.contains(r#""name":"world""#), "{output}");
+    }
+
+    // renders differently through `Display` and `Debug`
+    struct Version;
+
+    impl std::fmt::Display for Version {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("1.2")
+        }
+    }
+
+    impl std::fmt::Debug for Version {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("Version { major: 1, minor: 2 }")
+        }
+    }
+
+    #[test]
+    fn display_and_debug() {
+        // `%` values reach the visitor through `record_debug`, already wrapped so that
+        // their `Debug` is their `Display`, and are re-emitted with the text they rendered to
+        let output = capture(
+            fmt::format::DefaultFields::new(),
+            fmt::format().compact().without_time(),
+            |_| Some(Level::WARN),
+            || tracing::error!(display = %Version, debug = ?Version, text = %"text", "test"),
+        );
+
+        assert_eq!(
+            output,
+            " WARN tracing_rewrite::tests: test display=1.2 debug=Version { major: 1, minor: 2 } \
+             text=text\n"
+        );
+    }
+
+    #[test]
+    fn booleans_keep_their_type() {
+        let output = capture(
+            JsonFields::new(),
+            fmt::format().json().without_time(),
+            |_| Some(Level::ERROR),
+            || tracing::warn!(ready = true, name = "world", list = ?[1, 2], "x"),
+        );
+
+        assert!(output.contains(r#""level":"ERROR""#), "{output}");
+        assert!(output.contains(r#""ready":true"#), "{output}");
+        assert!(output.contains(r#""name":"world""#), "{output}");
+        assert!(output.contains(r#""list":"[1, 2]""#), "{output}");
+    }
+
+    #[test]
+    fn static_fields() {
+        const FIELDS: &[(&str, &str)] = &[("rewritten", "true"), ("by", "tracing-rewrite")];
+        let check =
+            |metadata: &Metadata<'_>| (*metadata.level() == Level::WARN).then_some(Level::ERROR);
+        let emit = || {
+            tracing::warn!(a = 1, "rewritten");
+            tracing::info!(a = 1, "untouched");
+        };
+
+        let compact =
+            super::EventFormatter::<10, _, _>::new(fmt::format().compact().without_time(), check)
+                .with_static_fields(FIELDS);
+        let output = capture_with(fmt::format::DefaultFields::new(), compact, emit);
+        assert_eq!(
+            output,
+            "ERROR tracing_rewrite::tests: rewritten a=1 rewritten=\"true\" by=\"tracing-rewrite\"\n \
+             INFO tracing_rewrite::tests: untouched a=1\n"
+        );
+
+        let json =
+            super::EventFormatter::<10, _, _>::new(fmt::format().json().without_time(), check)
+                .with_static_fields(FIELDS);
+        let output = capture_with(JsonFields::new(), json, emit);
+        let lines = output.lines().collect::<Vec<_>>();
+        assert!(
+            lines[0].contains(r#""fields":{"message":"rewritten","a":1,"rewritten":"true","by":"tracing-rewrite"}"#),
+            "{output}"
+        );
+        assert!(!lines[1].contains("rewritten\":"), "{output}");
+
+        // the fields need a slot of their own, evicting the last values of a full visitor
+        let small =
+            super::EventFormatter::<2, _, _>::new(fmt::format().compact().without_time(), check)
+                .with_static_fields(&FIELDS[..1]);
+        let output = capture_with(fmt::format::DefaultFields::new(), small, || {
+            tracing::warn!(a = 1, "rewritten")
+        });
+        assert_eq!(
+            output,
+            format!(
+                "ERROR tracing_rewrite::tests: {}=2 rewritten=\"true\"\n",
+                super::TRUNCATED_FIELD_NAME
+            )
+        );
+    }
+
+    #[test]
+    fn thread_field() {
+        let formatter = super::EventFormatter::<10, _, _>::new(
+            fmt::format().json().without_time(),
+            |metadata: &Metadata<'_>| (*metadata.level() == Level::WARN).then_some(Level::ERROR),
+        )
+        .with_thread_field()
+        .with_static_fields(&[("rewritten", "true")]);
+
+        let id = Mutex::new(None);
+        let output = capture_with(JsonFields::new(), formatter, || {
+            let dispatch = tracing::dispatcher::get_default(Clone::clone);
+            let emit = move || {
+                tracing::dispatcher::with_default(&dispatch, || {
+                    tracing::warn!("rewritten");
+                    tracing::info!("untouched");
+                })
+            };
+            let named = std::thread::Builder::new().name("worker-3".into());
+            named.spawn(emit.clone()).unwrap().join().unwrap();
+            let unnamed = std::thread::spawn(emit);
+            *id.lock().unwrap() = Some(unnamed.thread().id());
+            unnamed.join().unwrap();
+        });
+
+        let lines = output
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4, "{output}");
+        assert_eq!(lines[0]["fields"]["thread"], "worker-3");
+        assert_eq!(lines[0]["fields"]["rewritten"], "true");
+        let id = format!("{:?}", id.lock().unwrap().unwrap());
+        assert_eq!(lines[2]["fields"]["thread"], id.as_str());
+        for untouched in [&lines[1], &lines[3]] {
+            assert_eq!(untouched["level"], "INFO");
+            assert!(untouched["fields"].get("thread").is_none(), "{output}");
+        }
+    }
+
+    #[test]
+    fn floats_keep_their_type() {
+        let emit = || tracing::warn!(latency = 2.71, nan = f64::NAN, inf = f64::INFINITY, "x");
+
+        let output = capture(
+            JsonFields::new(),
+            fmt::format().json().without_time(),
+            |_| Some(Level::WARN),
+            emit,
+        );
+        // rewriting to the same level must give the same output of the original event
+        let original = capture(
+            JsonFields::new(),
+            fmt::format().json().without_time(),
+            |_| None::<Level>,
+            emit,
+        );
+
+        assert!(output.contains(r#""latency":2.71"#), "{output}");
+        assert_eq!(output, original);
+
+        let output = capture(
+            fmt::format::DefaultFields::new(),
+            fmt::format().compact().without_time(),
+            |_| Some(Level::WARN),
+            emit,
+        );
+        let original = capture(
+            fmt::format::DefaultFields::new(),
+            fmt::format().compact().without_time(),
+            |_| None::<Level>,
+            emit,
+        );
+
+        assert!(output.contains("nan=NaN inf=inf"), "{output}");
+        assert_eq!(output, original);
+    }
+
+    #[test]
+    fn errors_keep_their_sources() {
+        #[derive(Debug)]
+        struct Failure(Option<Box<Failure>>);
+
+        impl std::fmt::Display for Failure {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                if self.0.is_some() {
+                    f.write_str("outer")
+                } else {
+                    f.write_str("inner")
+                }
+            }
+        }
+
+        impl std::error::Error for Failure {
+            fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+                self.0.as_deref().map(|e| e as _)
+            }
+        }
+
+        let emit = || {
+            let err = Failure(Some(Box::new(Failure(None))));
+            tracing::error!(error = &err as &dyn std::error::Error, "failed")
+        };
+
+        let output = capture(
+            fmt::format::DefaultFields::new(),
+            fmt::format().compact().without_time(),
+            |_| Some(Level::ERROR),
+            emit,
+        );
+        let original = capture(
+            fmt::format::DefaultFields::new(),
+            fmt::format().compact().without_time(),
+            |_| None::<Level>,
+            emit,
+        );
+
+        assert!(
+            output.contains("error=outer error.sources=[inner]"),
+            "{output}"
+        );
+        assert_eq!(output, original);
+    }
+
+    #[test]
+    fn pretty() {
+        // the pretty formatter names every field, a value left in a spare slot of the visitor
+        // would show up as a field of its own
+        let emit = || {
+            tracing::info_span!("request", id = 7).in_scope(|| {
+                tracing::error!(signed = -1, flag = true, text = "plain", list = ?[1, 2], "test")
+            })
+        };
+        let capture_pretty = |check: fn(&Metadata<'static>) -> Option<Level>| {
+            capture(
+                fmt::format::PrettyFields::new(),
+                fmt::format().pretty().without_time(),
+                check,
+                emit,
+            )
+        };
+
+        let original = capture_pretty(|_| None);
+        assert_eq!(original, capture_pretty(|_| Some(Level::ERROR)));
+        assert_eq!(
+            original.replacen("ERROR", " WARN", 1),
+            capture_pretty(|_| Some(Level::WARN))
+        );
+        let lines = original.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4, "{original}");
+        assert_eq!(
+            lines[0],
+            "  ERROR tracing_rewrite::tests: test, signed: -1, flag: true, text: \"plain\", list: [1, 2]"
+        );
+        assert!(lines[1].starts_with("    at src/lib.rs:"), "{original}");
+        assert_eq!(
+            lines[2],
+            "    in tracing_rewrite::tests::request with id: 7"
+        );
+    }
+
+    #[test]
+    fn messages() {
+        // messages are recorded like tracing does, through `Debug` for format strings and `%` values,
+        // or as `&str` for string literals, so formatters special-casing `message` see the same types
+        fn emit() {
+            tracing::info!("plain");
+            tracing::info!("quoted \"{}\"\nsecond line", "argument");
+            tracing::info!(a = 1, "with {:?} and fields", [1, 2]);
+            tracing::info!(message = "literal");
+            tracing::info!(message = %"display");
+            tracing::info!(message = ?"debug");
+            tracing::info!(message = 42);
+        }
+        let compact = |check: fn(&Metadata<'static>) -> Option<Level>| {
+            capture(
+                fmt::format::DefaultFields::new(),
+                fmt::format().compact().without_time(),
+                check,
+                emit,
+            )
+        };
+        let pretty = |check: fn(&Metadata<'static>) -> Option<Level>| {
+            capture(
+                fmt::format::PrettyFields::new(),
+                fmt::format().pretty().without_time(),
+                check,
+                emit,
+            )
+        };
+        let json = |check: fn(&Metadata<'static>) -> Option<Level>| {
+            capture(
+                JsonFields::new(),
+                fmt::format().json().without_time(),
+                check,
+                emit,
+            )
+        };
+
+        // rewritten to the same level, so that the output is the same
+        let original = compact(|_| None);
+        assert_eq!(compact(|_| Some(Level::INFO)), original);
+        assert_eq!(pretty(|_| Some(Level::INFO)), pretty(|_| None));
+        assert_eq!(json(|_| Some(Level::INFO)), json(|_| None));
+        assert_eq!(
+            original,
+            " INFO tracing_rewrite::tests: plain\n INFO tracing_rewrite::tests: quoted \
+             \"argument\"\nsecond line\n INFO tracing_rewrite::tests: with [1, 2] and fields \
+             a=1\n INFO tracing_rewrite::tests: literal\n INFO tracing_rewrite::tests: display\n \
+             INFO tracing_rewrite::tests: \"debug\"\n INFO tracing_rewrite::tests: 42\n"
+        );
+    }
+
+    // nanoseconds elapsed since a fixed instant, comparable with the ones measured by the test
+    struct Elapsed(std::time::Instant);
+
+    impl fmt::time::FormatTime for Elapsed {
+        fn format_time(&self, w: &mut Writer<'_>) -> std::fmt::Result {
+            write!(w, "{}", self.0.elapsed().as_nanos())
+        }
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn timestamps() {
+        // the wrapped formatter timestamps the rewritten event while formatting it,
+        // on the thread emitting the original one, before `tracing::warn!` returns
+        let start = std::time::Instant::now();
+        let (mut before, mut after) = (0, 0);
+        let output = capture(
+            fmt::format::DefaultFields::new(),
+            fmt::format().compact().with_timer(Elapsed(start)),
+            |_| Some(Level::ERROR),
+            || {
+                before = start.elapsed().as_nanos();
+                tracing::warn!("test");
+                after = start.elapsed().as_nanos();
+            },
+        );
+
+        let (timestamp, rest) = output.split_once(' ').unwrap();
+        assert_eq!(rest, "ERROR tracing_rewrite::tests: test\n");
+        let timestamp = timestamp.parse::<u128>().unwrap();
+        assert!(
+            before <= timestamp && timestamp <= after,
+            "{before} {timestamp} {after}"
+        );
+    }
+
+    // fieldless callsite, like a span declared without fields
+    static FIELDLESS_META: Metadata<'static> = tracing_core::metadata! {
+        name: "fieldless",
+        target: "fieldless",
+        level: tracing::Level::DEBUG,
+        fields: &[],
+        callsite: &TEST_CALLSITE,
+        kind: tracing_core::Kind::EVENT,
+    };
+
+    #[test]
+    fn fieldless() {
+        let emit = || {
+            let values: [(&Field, Option<&dyn tracing::Value>); 0] = [];
+            Event::dispatch(&FIELDLESS_META, &FIELDLESS_META.fields().value_set(&values));
+        };
+
+        let output = capture(
+            fmt::format::DefaultFields::new(),
+            fmt::format().compact().without_time(),
+            |_| Some(Level::WARN),
+            emit,
+        );
+        assert_eq!(output, " WARN fieldless: \n");
+
+        // injected fields still need the visitor
+        let output = capture(
+            fmt::format::DefaultFields::new(),
+            fmt::format().compact().without_time(),
+            |_| {
+                Some(super::Rewrite {
+                    level: Some(Level::WARN),
+                    original_level: true,
+                    ..Default::default()
+                })
+            },
+            emit,
+        );
+        assert_eq!(output, " WARN fieldless: original_level=\"DEBUG\"\n");
+    }
+
+    #[test]
+    fn overflowing_fields_are_dropped() {
+        let output = capture(
+            fmt::format::DefaultFields::new(),
+            fmt::format().compact().without_time(),
+            |_| Some(Level::WARN),
+            || {
+                tracing::error!(
+                    a = 1,
+                    b = 2,
+                    c = 3,
+                    d = 4,
+                    e = 5,
+                    f = 6,
+                    g = 7,
+                    h = 8,
+                    i = 9,
+                    j = 10,
+                    k = 11,
+                    "test"
+                )
+            },
+        );
+
+        // 12 fields with 10 slots: 9 are kept and the last slot reports the other 3
+        assert!(
+            output.contains("WARN tracing_rewrite::tests: test a=1 b=2 c=3 d=4 e=5 f=6 g=7 h=8 fields_truncated=3"),
+            "{output}"
+        );
+
+        let output = capture(
+            fmt::format::DefaultFields::new(),
+            fmt::format().compact().without_time(),
+            |_| Some(Level::WARN),
+            || {
+                tracing::error!(
+                    a = 1,
+                    b = 2,
+                    c = 3,
+                    d = 4,
+                    e = 5,
+                    f = 6,
+                    g = 7,
+                    h = 8,
+                    i = 9,
+                    "test"
+                )
+            },
+        );
+
+        assert!(!output.contains("fields_truncated"), "{output}");
+    }
+
+    #[test]
+    fn check_with_fields() {
+        let output = capture_with(
+            fmt::format::DefaultFields::new(),
+            super::EventFormatter::<10, _, _>::with_fields(
+                fmt::format().compact().without_time(),
+                |_, fields: &super::FieldSnapshot<'_>| match fields.get("status") {
+                    Some(super::FieldValue::U64(status)) if status >= 500 => Some(Level::ERROR),
+                    _ => None,
+                },
+            ),
+            || {
+                tracing::info!(status = 200u64, "ok");
+                tracing::info!(status = 503u64, "unavailable");
+            },
+        );
+
+        assert!(
+            output.contains(" INFO tracing_rewrite::tests: ok status=200\n"),
+            "{output}"
+        );
+        assert!(
+            output.contains("ERROR tracing_rewrite::tests: unavailable status=503\n"),
+            "{output}"
+        );
+    }
+
+    #[test]
+    fn check_by_field_name() {
+        let output = capture_with(
+            fmt::format::DefaultFields::new(),
+            super::EventFormatter::<10, _, _>::with_fields(
+                fmt::format().compact().without_time(),
+                |_, fields: &super::FieldSnapshot<'_>| {
+                    // integer literals are recorded as i64
+                    let status = fields.get("http.status").and_then(|v| v.as_u64());
+                    (fields.value_for("http.method") == Some("POST") && status >= Some(500))
+                        .then_some(Level::ERROR)
+                },
+            ),
+            || {
+                tracing::warn!(http.status = 503, http.method = "POST", "failed");
+                tracing::warn!(http.status = 503, http.method = "GET", "failed");
+                tracing::warn!(http.status = 200, http.method = "POST", "ok");
+            },
+        );
+
+        let levels = levels(&output);
+        assert_eq!(levels, ["ERROR", "WARN", "WARN"], "{output}");
+    }
+
+    #[test]
+    fn no_phantom_fields() {
+        let output = capture_with(
+            JsonFields::new(),
+            super::DefaultEventFormatter::new(
+                fmt::format().json().without_time().flatten_event(true),
+                |_| Some(Level::WARN),
+            ),
+            || tracing::error!(a = 1, b = "two", "test"),
+        );
+
+        assert_eq!(
+            output,
+            r#"{"level":"WARN","message":"test","a":1,"b":"two","target":"tracing_rewrite::tests"}"#
+                .to_owned() + "\n"
+        );
+    }
+
+    #[test]
+    fn redaction() {
+        let output = capture_with(
+            fmt::format::DefaultFields::new(),
+            super::EventFormatter::<10, _, _>::with_transform(
+                fmt::format().compact().without_time(),
+                |_| Some(super::Rewrite::default()),
+                |field, value| {
+                    (field.name() == "email" && value.contains('@')).then(|| "***".to_owned())
+                },
+            ),
+            || {
+                tracing::info!(email = "user@example.com", user = ?"user", id = 1, "login");
+                tracing::info!(email = ?"user@example.com", "debug");
+                tracing::info!(email = "none", "kept");
+            },
+        );
+
+        assert_eq!(
+            output,
+            " INFO tracing_rewrite::tests: login email=\"***\" user=\"user\" id=1\n INFO \
+             tracing_rewrite::tests: debug email=***\n INFO tracing_rewrite::tests: kept \
+             email=\"none\"\n"
+        );
+    }
+
+    // `Debug` output spanning several lines, like `{:#?}` does
+    struct Multiline;
+
+    impl std::fmt::Debug for Multiline {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("first\nsecond")
+        }
+    }
+
+    #[test]
+    fn tiers_keep_every_field() {
+        let formatter =
+            super::EventFormatter::<100, _, _>::new(fmt::format().compact().without_time(), |_| {
+                Some(super::Rewrite {
+                    level: Some(Level::WARN),
+                    original_level: true,
+                    ..Default::default()
+                })
+            });
+        let output = capture_with(fmt::format::DefaultFields::new(), formatter, || {
+            // each one a field past a tier, once the injected field is counted
+            tracing::error!(a = 1, b = 2, c = 3, "4");
+            tracing::error!(
+                a = 1,
+                b = 2,
+                c = 3,
+                d = 4,
+                e = 5,
+                f = 6,
+                g = 7,
+                h = 8,
+                i = 9,
+                j = 10,
+                k = 11,
+                l = 12,
+                m = 13,
+                n = 14,
+                o = 15,
+                "16"
+            );
+        });
+
+        assert!(!output.contains(super::TRUNCATED_FIELD_NAME), "{output}");
+        assert!(
+            output.contains(": 4 a=1 b=2 c=3 original_level=\"ERROR\"\n"),
+            "{output}"
+        );
+        assert!(
+            output.contains(" n=14 o=15 original_level=\"ERROR\"\n"),
+            "{output}"
+        );
+    }
+
+    #[test]
+    fn multiline_values() {
+        let emit = || tracing::warn!(data = ?vec![1, 2, 3], multiline = ?Multiline, "x");
+        let compact = || fmt::format().compact().without_time();
+        let json = || fmt::format().json().without_time().flatten_event(true);
+        let rewrite = |_: &Metadata<'static>| Some(super::Rewrite::default());
+
+        // values are formatted exactly as the wrapped formatter would
+        let original = capture_with(fmt::format::DefaultFields::new(), compact(), emit);
+        assert_eq!(
+            original,
+            " WARN tracing_rewrite::tests: x data=[1, 2, 3] multiline=first\nsecond\n"
+        );
+        let rewritten = capture_with(
+            fmt::format::DefaultFields::new(),
+            super::DefaultEventFormatter::new(compact(), rewrite),
+            emit,
+        );
+        assert_eq!(rewritten, original);
+
+        let original = capture_with(JsonFields::new(), json(), emit);
+        let rewritten = capture_with(
+            JsonFields::new(),
+            super::DefaultEventFormatter::new(json(), rewrite),
+            emit,
+        );
+        assert_eq!(rewritten, original);
+        assert!(
+            rewritten.contains(r#""multiline":"first\nsecond""#),
+            "{rewritten}"
+        );
+
+        // line-oriented outputs can escape them with a transform
+        let escaped = capture_with(
+            fmt::format::DefaultFields::new(),
+            super::DefaultEventFormatter::with_transform(compact(), rewrite, |_, value| {
+                value.contains('\n').then(|| value.replace('\n', "\\n"))
+            }),
+            emit,
+        );
+        assert_eq!(
+            escaped,
+            " WARN tracing_rewrite::tests: x data=[1, 2, 3] multiline=first\\nsecond\n"
+        );
+    }
+
+    fn promote_debug_spans<const VISITOR_SIZE: usize>() -> String {
+        capture_spans(
+            fmt::format::DefaultFields::new(),
+            super::EventFormatter::<VISITOR_SIZE, _, _>::new(
+                fmt::format().compact().without_time(),
+                |metadata: &Metadata<'static>| {
+                    (metadata.is_span() && *metadata.level() == Level::DEBUG).then_some(Level::WARN)
+                },
+            ),
+            FmtSpan::NEW | FmtSpan::CLOSE,
+            || {
+                let span = tracing::debug_span!("promoted", id = 1);
+                span.in_scope(|| tracing::debug!("inside"));
+            },
+        )
+    }
+
+    #[test]
+    fn span_events() {
+        let output = promote_debug_spans::<10>();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], " WARN promoted: tracing_rewrite::tests: new id=1");
+        assert_eq!(
+            lines[1],
+            "DEBUG promoted: tracing_rewrite::tests: inside id=1"
+        );
+        assert!(lines[2].starts_with(" WARN promoted: tracing_rewrite::tests: close time.busy="));
+    }
+
+    #[test]
+    fn check_spans() {
+        let formatter = super::EventFormatter::builder()
+            .formatter(fmt::format().compact().without_time())
+            .check(|_| Some(Level::ERROR))
+            .check_spans(|metadata| (*metadata.level() == Level::TRACE).then_some(Level::DEBUG))
+            .build();
+
+        let output = capture_spans(
+            fmt::format::DefaultFields::new(),
+            formatter,
+            FmtSpan::FULL,
+            || {
+                let span = tracing::trace_span!("promoted", id = 1);
+                span.in_scope(|| tracing::trace!("inside"));
+            },
+        );
+
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 5, "{output}");
+        assert_eq!(lines[0], "DEBUG promoted: tracing_rewrite::tests: new id=1");
+        assert_eq!(
+            lines[1],
+            "DEBUG promoted: tracing_rewrite::tests: enter id=1"
+        );
+        // events go to the other check
+        assert_eq!(
+            lines[2],
+            "ERROR promoted: tracing_rewrite::tests: inside id=1"
+        );
+        assert_eq!(
+            lines[3],
+            "DEBUG promoted: tracing_rewrite::tests: exit id=1"
+        );
+        assert!(lines[4].starts_with("DEBUG promoted: tracing_rewrite::tests: close time.busy="));
+    }
+
+    // field formatter relying on the type of the values: numbers formatted as text are an error
+    struct StrictFields;
+
+    impl<'writer> FormatFields<'writer> for StrictFields {
+        fn format_fields<R: tracing_subscriber::field::RecordFields>(
+            &self,
+            writer: Writer<'writer>,
+            fields: R,
+        ) -> std::fmt::Result {
+            struct Strict<'writer> {
+                writer: Writer<'writer>,
+                result: std::fmt::Result,
+            }
+
+            impl Strict<'_> {
+                fn write(&mut self, field: &Field, kind: &str, value: &dyn std::fmt::Debug) {
+                    if self.result.is_ok() {
+                        self.result = write!(self.writer, " {}:{kind}={value:?}", field.name());
+                    }
+                }
+
+                fn text(&mut self, field: &Field, kind: &str, value: &str) {
+                    if value.parse::<f64>().is_ok() {
+                        self.result = Err(std::fmt::Error);
+                    }
+                    self.write(field, kind, &value);
+                }
+            }
+
+            impl tracing::field::Visit for Strict<'_> {
+                fn record_i64(&mut self, field: &Field, value: i64) {
+                    self.write(field, "i64", &value);
+                }
+
+                fn record_u64(&mut self, field: &Field, value: u64) {
+                    self.write(field, "u64", &value);
+                }
+
+                fn record_f64(&mut self, field: &Field, value: f64) {
+                    self.write(field, "f64", &value);
+                }
+
+                fn record_str(&mut self, field: &Field, value: &str) {
+                    self.text(field, "str", value);
+                }
+
+                fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                    self.text(field, "debug", &format!("{value:?}"));
+                }
+            }
+
+            let mut strict = Strict {
+                writer,
+                result: Ok(()),
+            };
+            fields.record(&mut strict);
+            strict.result
+        }
+    }
+
+    #[test]
+    fn typed_field_formatters() {
+        let emit = || {
+            tracing::error!(
+                signed = -1,
+                unsigned = 2u64,
+                float = 0.5,
+                text = "a",
+                "test"
+            )
+        };
+        let original = capture_with(StrictFields, fmt::format().compact().without_time(), emit);
+        let output = capture_with(
+            StrictFields,
+            super::DefaultEventFormatter::new(fmt::format().compact().without_time(), |_| {
+                Some(Level::WARN)
+            }),
+            emit,
+        );
+
+        assert_eq!(
+            original,
+            "ERROR tracing_rewrite::tests:  message:debug=\"test\" signed:i64=-1 unsigned:u64=2 \
+             float:f64=0.5 text:str=\"a\"\n"
+        );
+        assert_eq!(output, original.replace("ERROR", " WARN"));
+    }
+
+    #[test]
+    fn spans_passthrough() {
+        fn capture<E>(event_format: E) -> String
+        where
+            E: FormatEvent<Registry, fmt::format::DefaultFields> + Send + Sync + 'static,
+        {
+            // close events carry timings, that change at every run
+            capture_spans(
+                fmt::format::DefaultFields::new(),
+                event_format,
+                FmtSpan::NEW | FmtSpan::ENTER | FmtSpan::EXIT,
+                || {
+                    let span = tracing::debug_span!("span", id = 1);
+                    span.in_scope(|| tracing::debug!("inside"));
+                },
+            )
+        }
+
+        let original = capture(fmt::format().compact().without_time());
+        let output = capture(
+            super::EventFormatter::builder()
+                .formatter(fmt::format().compact().without_time())
+                .check(|_| Some(Level::WARN))
+                .rewrite_spans(false)
+                .build(),
+        );
+
+        assert_eq!(
+            output,
+            original.replace(
+                "DEBUG span: tracing_rewrite::tests: inside",
+                " WARN span: tracing_rewrite::tests: inside"
+            )
+        );
+        assert_eq!(original.matches("DEBUG").count(), 4, "{original}");
+    }
+
+    #[test]
+    fn span_events_are_truncated() {
+        // the close event has more fields than the span it comes from
+        let output = promote_debug_spans::<2>();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[2],
+            " WARN promoted: tracing_rewrite::tests: close fields_truncated=2 id=1"
+        );
+    }
+
+    #[test]
+    fn default_visitor_size() {
+        let output = capture_with(
+            fmt::format::DefaultFields::new(),
+            super::DefaultEventFormatter::new(fmt::format().compact().without_time(), |_| {
+                Some(Level::WARN)
+            }),
+            || {
+                tracing::error!(
+                    a = 1,
+                    b = 2,
+                    c = 3,
+                    d = 4,
+                    e = 5,
+                    f = 6,
+                    g = 7,
+                    h = 8,
+                    i = 9,
+                    j = 10,
+                    k = 11,
+                    "test"
+                )
+            },
+        );
+
+        assert!(
+            output.contains(
+                "WARN tracing_rewrite::tests: test a=1 b=2 c=3 d=4 e=5 f=6 g=7 h=8 i=9 j=10 k=11\n"
+            ),
+            "{output}"
+        );
+    }
 }
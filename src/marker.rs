@@ -0,0 +1,329 @@
+use std::fmt::{self, Write};
+
+use tracing::Metadata;
+use tracing_subscriber::fmt::format::Writer;
+
+use crate::{Decoration, PostFormat, Rewrite};
+
+/// Writes the markers requested by `rewrite` around what `f` writes.
+///
+/// The prefix goes straight to `writer`, the suffix needs to intercept the trailing newline,
+/// so when a suffix is requested `f` gets a writer without ANSI support.
+pub fn with_markers(
+    rewrite: &Rewrite,
+    mut writer: Writer<'_>,
+    f: impl FnOnce(Writer<'_>) -> fmt::Result,
+) -> fmt::Result {
+    if let Some(prefix) = rewrite.prefix {
+        writer.write_str(prefix)?;
+    }
+
+    match rewrite.suffix {
+        Some(suffix) => with_suffix(writer, suffix, f),
+        None => f(writer),
+    }
+}
+
+/// Writes `from=DEBUG to=WARN callsite="..."` after what `f` writes, to see at a glance
+/// which events have been rewritten.
+///
+/// Only text output is annotated: JSON objects are written as they are, so that structured
+/// output stays valid. The output is buffered to tell them apart, so `f` gets a writer
+/// without ANSI support.
+#[cfg(feature = "trace_rewrites")]
+pub fn with_trace(
+    metadata: &Metadata<'_>,
+    rewrite: &Rewrite,
+    mut writer: Writer<'_>,
+    f: impl FnOnce(Writer<'_>) -> fmt::Result,
+) -> fmt::Result {
+    let mut buffer = String::new();
+    f(Writer::new(&mut buffer))?;
+    if buffer.starts_with('{') {
+        return writer.write_str(&buffer);
+    }
+
+    let from = metadata.level();
+    let to = rewrite.level.as_ref().unwrap_or(from);
+    let trace = format_args!(" from={from} to={to} callsite={:?}", metadata.name());
+    with_suffix(writer, trace, |mut writer| writer.write_str(&buffer))
+}
+
+#[cfg(not(feature = "trace_rewrites"))]
+pub fn with_trace(
+    _: &Metadata<'_>,
+    _: &Rewrite,
+    writer: Writer<'_>,
+    f: impl FnOnce(Writer<'_>) -> fmt::Result,
+) -> fmt::Result {
+    f(writer)
+}
+
+/// Hands what `f` writes to `post_format` before writing it to `writer`, when there is one.
+///
+/// The output is buffered into a `String`, so `f` gets a writer without ANSI support.
+pub fn with_post_format(
+    post_format: Option<&PostFormat>,
+    mut writer: Writer<'_>,
+    f: impl FnOnce(Writer<'_>) -> fmt::Result,
+) -> fmt::Result {
+    let Some(post_format) = post_format else {
+        return f(writer);
+    };
+
+    let mut buffer = String::new();
+    f(Writer::new(&mut buffer))?;
+    post_format(&mut buffer);
+    writer.write_str(&buffer)
+}
+
+/// Writes what `f` writes decorated as requested by `decoration`.
+///
+/// Like with markers, when an indentation or a suffix is requested `f` gets a writer without ANSI support.
+pub fn with_decoration(
+    decoration: &Decoration,
+    mut writer: Writer<'_>,
+    f: impl FnOnce(Writer<'_>) -> fmt::Result,
+) -> fmt::Result {
+    if let Some(prefix) = decoration.prefix {
+        writer.write_str(prefix)?;
+    }
+
+    let indented = |writer: Writer<'_>| match decoration.indent {
+        Some(indent) => {
+            let mut indented = Indented {
+                writer,
+                indent,
+                line_start: true,
+            };
+            f(Writer::new(&mut indented))
+        }
+        None => f(writer),
+    };
+    match decoration.suffix {
+        Some(suffix) => with_suffix(writer, suffix, indented),
+        None => indented(writer),
+    }
+}
+
+// writes `suffix` before the trailing newline of what `f` writes
+fn with_suffix(
+    writer: Writer<'_>,
+    suffix: impl fmt::Display,
+    f: impl FnOnce(Writer<'_>) -> fmt::Result,
+) -> fmt::Result {
+    let mut suffixed = Suffixed {
+        writer,
+        newline: false,
+    };
+    f(Writer::new(&mut suffixed))?;
+    write!(suffixed.writer, "{suffix}")?;
+    if suffixed.newline {
+        suffixed.writer.write_char('\n')?;
+    }
+    Ok(())
+}
+
+// holds back the last newline, so that the suffix can be written before it
+struct Suffixed<'a> {
+    writer: Writer<'a>,
+    newline: bool,
+}
+
+impl Write for Suffixed<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if s.is_empty() {
+            return Ok(());
+        }
+
+        // the newline held back wasn't the last one
+        if self.newline {
+            self.writer.write_char('\n')?;
+        }
+
+        match s.strip_suffix('\n') {
+            Some(s) => {
+                self.newline = true;
+                self.writer.write_str(s)
+            }
+            None => {
+                self.newline = false;
+                self.writer.write_str(s)
+            }
+        }
+    }
+}
+
+// writes `indent` at the start of every line, the one after the trailing newline excluded
+struct Indented<'a> {
+    writer: Writer<'a>,
+    indent: &'static str,
+    line_start: bool,
+}
+
+impl Write for Indented<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for line in s.split_inclusive('\n') {
+            if self.line_start {
+                self.writer.write_str(self.indent)?;
+            }
+            self.writer.write_str(line)?;
+            self.line_start = line.ends_with('\n');
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::Level;
+    use tracing_subscriber::fmt::{self, format::DefaultFields};
+
+    use crate::{tests::capture, Decision, Decoration, Rewrite};
+
+    #[cfg(feature = "trace_rewrites")]
+    #[test]
+    fn trace_rewrites() {
+        use std::cell::Cell;
+
+        use tracing_subscriber::fmt::format::JsonFields;
+
+        use crate::{tests::Buffer, EventFormatter};
+
+        // the shared helpers drop the annotations, these tests need them
+        fn traced<N>(
+            fields: N,
+            formatter: impl fmt::FormatEvent<tracing_subscriber::Registry, N> + Send + Sync + 'static,
+            f: impl FnOnce(),
+        ) -> String
+        where
+            N: for<'a> fmt::FormatFields<'a> + Send + Sync + 'static,
+        {
+            let buffer = Buffer::default();
+            let subscriber = fmt::Subscriber::builder()
+                .with_max_level(Level::TRACE)
+                .with_ansi(false)
+                .with_writer(buffer.clone())
+                .fmt_fields(fields)
+                .event_format(EventFormatter::<10, _, _>::new(formatter, |metadata| {
+                    (*metadata.level() == Level::DEBUG).then_some(Rewrite {
+                        level: Some(Level::WARN),
+                        suffix: Some(" [/REWRITTEN]"),
+                        ..Default::default()
+                    })
+                }))
+                .finish();
+            tracing::subscriber::with_default(subscriber, f);
+            let output = buffer.0.lock().unwrap().clone();
+            String::from_utf8(output).unwrap()
+        }
+
+        let line = Cell::new(0);
+        #[rustfmt::skip]
+        let emit = || {
+            line.set(line!()); tracing::debug!("rewritten");
+            tracing::info!("untouched");
+        };
+
+        let output = traced(
+            DefaultFields::new(),
+            fmt::format().compact().without_time(),
+            emit,
+        );
+        assert_eq!(
+            output,
+            format!(
+                " WARN tracing_rewrite::marker::tests: rewritten from=DEBUG to=WARN \
+                 callsite=\"event src/marker.rs:{}\" [/REWRITTEN]\n INFO \
+                 tracing_rewrite::marker::tests: untouched\n",
+                line.get()
+            )
+        );
+
+        // JSON objects aren't annotated, they would no longer be valid
+        let output = traced(JsonFields::new(), fmt::format().json().without_time(), emit);
+        assert!(!output.contains("from="), "{output}");
+    }
+
+    #[test]
+    fn markers() {
+        let output = capture(
+            DefaultFields::new(),
+            fmt::format().compact().without_time(),
+            |metadata| {
+                metadata
+                    .fields()
+                    .field("escalated")
+                    .is_some()
+                    .then_some(Rewrite {
+                        prefix: Some("[ESCALATED] "),
+                        suffix: Some(" [/ESCALATED]"),
+                        ..Default::default()
+                    })
+            },
+            || {
+                tracing::warn!(escalated = true, "first");
+                tracing::warn!("second");
+            },
+        );
+
+        assert_eq!(
+            output,
+            "[ESCALATED]  WARN tracing_rewrite::marker::tests: first escalated=true [/ESCALATED]\n \
+             WARN tracing_rewrite::marker::tests: second\n"
+        );
+    }
+
+    #[test]
+    fn multiline() {
+        let output = capture(
+            DefaultFields::new(),
+            fmt::format().compact().without_time(),
+            |_| {
+                Some(Rewrite {
+                    suffix: Some(" <"),
+                    ..Default::default()
+                })
+            },
+            || tracing::warn!("first\nsecond"),
+        );
+
+        assert_eq!(
+            output,
+            " WARN tracing_rewrite::marker::tests: first\nsecond <\n"
+        );
+    }
+
+    #[test]
+    fn decorations() {
+        let output = capture(
+            DefaultFields::new(),
+            fmt::format().compact().without_time(),
+            |metadata| match *metadata.level() {
+                Level::DEBUG => Decision::Decorate(Decoration {
+                    indent: Some("    "),
+                    ..Default::default()
+                }),
+                Level::ERROR => Decision::Decorate(Decoration {
+                    indent: Some("> "),
+                    prefix: Some("\x1b[31m"),
+                    suffix: Some("\x1b[0m"),
+                }),
+                _ => Decision::Keep,
+            },
+            || {
+                tracing::debug!("first\nsecond");
+                tracing::error!(code = 1, "failed");
+                tracing::info!("untouched");
+            },
+        );
+
+        // the level is the original one
+        assert_eq!(
+            output,
+            "    DEBUG tracing_rewrite::marker::tests: first\n    second\n\x1b[31m> ERROR \
+             tracing_rewrite::marker::tests: failed code=1\x1b[0m\n INFO \
+             tracing_rewrite::marker::tests: untouched\n"
+        );
+    }
+}
@@ -0,0 +1,566 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use tracing::{field::FieldSet, Metadata};
+use tracing_core::Kind;
+
+use crate::{
+    fieldset, rewrite::MetadataKind, Rewrite, BACKTRACE_FIELD_NAME, ORIGINAL_LEVEL_FIELD_NAME,
+    TRUNCATED_FIELD_NAME,
+};
+
+// metadata lifecycle depends on the `MetadataStrategy`, whose default depends on the enabled features:
+// * `cache_metadata`: leaked once per callsite and rewrite, then reused
+// * `arena`: allocated in a thread-local arena, reset once the outermost rewrite is done
+// * `i_really_want_memory_leak`: leaked and never freed
+// * none: boxed and freed once the event has been handled
+
+/// Fields appended to the ones of the original callsite, in this order
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Injected {
+    /// `TRUNCATED_FIELD_NAME`, when the event has more fields than the visitor
+    pub truncated: bool,
+    /// `ORIGINAL_LEVEL_FIELD_NAME`, when requested by the rewrite
+    pub original_level: bool,
+    /// `BACKTRACE_FIELD_NAME`, when a backtrace has been captured
+    pub backtrace: bool,
+    /// constant fields of the formatter, after every other one
+    pub statics: &'static [&'static str],
+}
+
+impl Injected {
+    fn names(self) -> &'static [&'static str] {
+        const T: &str = TRUNCATED_FIELD_NAME;
+        const O: &str = ORIGINAL_LEVEL_FIELD_NAME;
+        const B: &str = BACKTRACE_FIELD_NAME;
+        match (self.truncated, self.original_level, self.backtrace) {
+            (false, false, false) => &[],
+            (true, false, false) => &[T],
+            (false, true, false) => &[O],
+            (true, true, false) => &[T, O],
+            (false, false, true) => &[B],
+            (true, false, true) => &[T, B],
+            (false, true, true) => &[O, B],
+            (true, true, true) => &[T, O, B],
+        }
+    }
+}
+
+/// How rewritten metadata is allocated and released, see
+/// [`EventFormatterBuilder::metadata_strategy`](crate::EventFormatterBuilder::metadata_strategy).
+///
+/// `Event` needs `&'static` metadata: either it's leaked, or it's freed after the event has been
+/// handled and the inner formatter or layer must not keep the reference past that point.
+/// Formatters forwarding events with
+/// [`EventFormatterBuilder::forward_to`](crate::EventFormatterBuilder::forward_to) never free it,
+/// `Free` becomes `Cache`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MetadataStrategy {
+    /// Allocated for every rewritten event and freed once the event has been handled,
+    /// in a thread-local arena with the `arena` feature, boxed otherwise
+    Free,
+    /// Leaked for every rewritten event and never freed, memory grows with every rewritten event
+    Leak,
+    /// Leaked once per callsite and rewrite, then reused, memory grows with the number of
+    /// different rewrites of every callsite
+    Cache,
+}
+
+impl Default for MetadataStrategy {
+    /// `Cache` with the `cache_metadata` feature, otherwise `Leak` with `i_really_want_memory_leak`
+    /// but without `arena`, otherwise `Free`
+    fn default() -> Self {
+        if cfg!(feature = "cache_metadata") {
+            MetadataStrategy::Cache
+        } else if cfg!(all(
+            feature = "i_really_want_memory_leak",
+            not(feature = "arena")
+        )) {
+            MetadataStrategy::Leak
+        } else {
+            MetadataStrategy::Free
+        }
+    }
+}
+
+impl MetadataStrategy {
+    /// Returns the rewritten metadata, with the `injected` fields appended.
+    ///
+    /// The guard must be kept alive until the event has been handled.
+    pub(crate) fn rewritten(
+        self,
+        metadata: &'static Metadata<'static>,
+        rewrite: &Rewrite,
+        injected: Injected,
+    ) -> Rewritten {
+        match self {
+            #[cfg(not(feature = "arena"))]
+            MetadataStrategy::Free => {
+                Rewritten::Boxed(Box::new(build(metadata, rewrite, injected)))
+            }
+            #[cfg(feature = "arena")]
+            MetadataStrategy::Free => {
+                Rewritten::Arena(arena::alloc(build(metadata, rewrite, injected)))
+            }
+            MetadataStrategy::Leak => Rewritten::Leaked(leak(metadata, rewrite, injected)),
+            MetadataStrategy::Cache => {
+                Rewritten::Leaked(cache::get_or_leak(metadata, rewrite, injected))
+            }
+        }
+    }
+}
+
+/// Rewritten metadata, released when dropped unless it has been leaked
+pub enum Rewritten {
+    #[cfg(not(feature = "arena"))]
+    Boxed(Box<Metadata<'static>>),
+    #[cfg(feature = "arena")]
+    Arena(&'static Metadata<'static>),
+    Leaked(&'static Metadata<'static>),
+}
+
+impl Rewritten {
+    pub fn get(&self) -> &'static Metadata<'static> {
+        match self {
+            // Safety: the box is freed only when the guard is dropped, after the event has been handled,
+            // and the inner formatter must not keep the reference past that point
+            #[cfg(not(feature = "arena"))]
+            Rewritten::Boxed(owned) => unsafe { &*(&**owned as *const Metadata<'static>) },
+            #[cfg(feature = "arena")]
+            Rewritten::Arena(metadata) => metadata,
+            Rewritten::Leaked(metadata) => metadata,
+        }
+    }
+}
+
+#[cfg(feature = "arena")]
+impl Drop for Rewritten {
+    fn drop(&mut self) {
+        if let Rewritten::Arena(_) = self {
+            arena::release()
+        }
+    }
+}
+
+#[cfg(not(feature = "safe_fields"))]
+const _: () = assert!(
+    std::mem::size_of::<FieldSet>()
+        == std::mem::size_of::<(&'static [&'static str], tracing::callsite::Identifier)>(),
+    "FieldSet layout changed, enable the `safe_fields` feature"
+);
+
+#[cfg(not(feature = "safe_fields"))]
+fn clone_fields(metadata: &Metadata<'_>) -> FieldSet {
+    // Safety: at the moment of writing this code, FieldSet is made like
+    // ```rust
+    // pub struct FieldSet {
+    //   names: &'static [&'static str],
+    //   callsite: callsite::Identifier,
+    // }
+    // ```
+    // and Identifier is make like
+    // ```rust
+    // #[derive(Clone)]
+    // pub struct Identifier(
+    //   #[doc(hidden)]
+    //   pub &'static dyn Callsite,
+    // );
+    // ```
+    // that means we can copy the static references without causing any UB
+    unsafe { std::mem::transmute_copy::<FieldSet, FieldSet>(metadata.fields()) }
+}
+
+#[cfg(feature = "safe_fields")]
+fn clone_fields(metadata: &Metadata<'_>) -> FieldSet {
+    // FieldSet names aren't reachable through the public API, so they are collected
+    // and interned once per callsite, without relying on the layout of FieldSet
+    fieldset::extend(metadata, &[], &[])
+}
+
+fn build(
+    metadata: &'static Metadata<'static>,
+    rewrite: &Rewrite,
+    injected: Injected,
+) -> Metadata<'static> {
+    let fields = match (injected.names(), injected.statics) {
+        ([], []) => clone_fields(metadata),
+        (names, statics) => fieldset::extend(metadata, names, statics),
+    };
+
+    Metadata::new(
+        rewrite.name.unwrap_or(metadata.name()),
+        rewrite.target.unwrap_or(metadata.target()),
+        rewrite.level.unwrap_or(*metadata.level()),
+        rewrite.file.or(metadata.file()),
+        rewrite.line.or(metadata.line()),
+        rewrite.module_path.or(metadata.module_path()),
+        fields,
+        kind(metadata, rewrite),
+    )
+}
+
+fn kind(metadata: &Metadata<'_>, rewrite: &Rewrite) -> Kind {
+    match rewrite.kind {
+        Some(MetadataKind::Event) => Kind::EVENT,
+        Some(MetadataKind::Span) => Kind::SPAN,
+        None if metadata.is_span() => Kind::SPAN,
+        // only events get rewritten, so anything unknown is reported as an event rather than aborting
+        None => Kind::EVENT,
+    }
+}
+
+// whether `build` would return the same as `rewritten` out of `metadata`, without building it
+fn is_built_from(
+    rewritten: &Metadata<'_>,
+    metadata: &Metadata<'_>,
+    rewrite: &Rewrite,
+    injected: Injected,
+) -> bool {
+    let (fields, source) = (rewritten.fields(), metadata.fields());
+    rewritten.name() == rewrite.name.unwrap_or(metadata.name())
+        && rewritten.target() == rewrite.target.unwrap_or(metadata.target())
+        && *rewritten.level() == rewrite.level.unwrap_or(*metadata.level())
+        && rewritten.file() == rewrite.file.or(metadata.file())
+        && rewritten.line() == rewrite.line.or(metadata.line())
+        && rewritten.module_path() == rewrite.module_path.or(metadata.module_path())
+        && rewritten.is_span() == kind(metadata, rewrite).is_span()
+        && fields.len() == source.len() + injected.names().len() + injected.statics.len()
+        && source
+            .iter()
+            .zip(fields.iter())
+            .all(|(a, b)| a.name() == b.name())
+}
+
+fn leak(
+    metadata: &'static Metadata<'static>,
+    rewrite: &Rewrite,
+    injected: Injected,
+) -> &'static Metadata<'static> {
+    // here we are leaking memory, but should be mainly references
+    count_leak();
+    Box::leak::<'static>(Box::new(build(metadata, rewrite, injected)))
+}
+
+static LEAKED: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of rewritten metadata leaked so far, to monitor the memory they take.
+///
+/// Metadata is leaked once per callsite and rewrite with [`MetadataStrategy::Cache`], once per rewritten
+/// event with [`MetadataStrategy::Leak`], and with `arena` only for the events rewritten while
+/// a thread is shutting down. Reaching [`LEAKED_METADATA_WARNING`](crate::LEAKED_METADATA_WARNING) prints a warning to stderr, once.
+pub fn leaked_metadata_count() -> usize {
+    LEAKED.load(Ordering::Relaxed)
+}
+
+fn count_leak() {
+    use crate::LEAKED_METADATA_WARNING;
+
+    // only the increment reaching the threshold warns, so it happens once
+    if LEAKED.fetch_add(1, Ordering::Relaxed) + 1 == LEAKED_METADATA_WARNING {
+        eprintln!(
+            "tracing-rewrite: {LEAKED_METADATA_WARNING} rewritten metadata have been leaked and \
+             are never freed. This warning is printed only once."
+        );
+    }
+}
+
+#[cfg(feature = "arena")]
+mod arena {
+    use std::cell::{Cell, RefCell};
+
+    use bumpalo::Bump;
+    use tracing::Metadata;
+
+    struct Arena {
+        bump: RefCell<Bump>,
+        // nested rewrites (e.g. stacked formatters) share the arena, the outermost one resets it
+        depth: Cell<usize>,
+    }
+
+    thread_local! {
+        static ARENA: Arena = Arena {
+            bump: RefCell::new(Bump::new()),
+            depth: Cell::new(0),
+        };
+    }
+
+    pub fn alloc(metadata: Metadata<'static>) -> &'static Metadata<'static> {
+        let mut metadata = Some(metadata);
+        ARENA
+            .try_with(|arena| {
+                arena.depth.set(arena.depth.get() + 1);
+                let allocated: *const Metadata<'static> =
+                    arena.bump.borrow().alloc(metadata.take().unwrap());
+                // Safety: the arena is reset only when every rewritten event of this thread
+                // has been handled, the inner formatter must not keep the reference past that point
+                unsafe { &*allocated }
+            })
+            // the thread is shutting down, nothing better to do than leaking
+            .unwrap_or_else(|_| {
+                super::count_leak();
+                Box::leak(Box::new(metadata.take().unwrap()))
+            })
+    }
+
+    pub fn release() {
+        let _ = ARENA.try_with(|arena| {
+            let depth = arena.depth.get() - 1;
+            arena.depth.set(depth);
+            if depth == 0 {
+                arena.bump.borrow_mut().reset();
+            }
+        });
+    }
+}
+
+mod cache {
+    use std::{
+        collections::HashMap,
+        sync::{OnceLock, RwLock},
+    };
+
+    use tracing::{callsite::Identifier, Metadata};
+
+    use super::Injected;
+    use crate::Rewrite;
+
+    type Key = (Identifier, Rewrite, Injected);
+
+    // stacked formatters hand the same callsite with metadata already rewritten by the outer ones,
+    // so a key can have more than one rewritten metadata
+    static CACHE: OnceLock<RwLock<HashMap<Key, Vec<&'static Metadata<'static>>>>> = OnceLock::new();
+
+    pub fn get_or_leak(
+        metadata: &'static Metadata<'static>,
+        rewrite: &Rewrite,
+        injected: Injected,
+    ) -> &'static Metadata<'static> {
+        let cache = CACHE.get_or_init(Default::default);
+        let key = (metadata.callsite(), *rewrite, injected);
+        let find = |cached: &Vec<&'static Metadata<'static>>| {
+            cached
+                .iter()
+                .find(|cached| super::is_built_from(cached, metadata, rewrite, injected))
+                .copied()
+        };
+
+        let cached = cache.read().unwrap_or_else(|e| e.into_inner());
+        if let Some(cached) = cached.get(&key).and_then(find) {
+            return cached;
+        }
+        drop(cached);
+
+        let mut cache = cache.write().unwrap_or_else(|e| e.into_inner());
+        let cached = cache.entry(key).or_default();
+        if let Some(cached) = find(cached) {
+            return cached;
+        }
+        let leaked = super::leak(metadata, rewrite, injected);
+        cached.push(leaked);
+        leaked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tracing::{callsite::Identifier, Event, Level, Metadata, Subscriber, Value};
+    use tracing_subscriber::{
+        fmt::{self, format::DefaultFields},
+        layer::Context,
+        prelude::*,
+        registry::Registry,
+        Layer,
+    };
+
+    use super::MetadataStrategy;
+    use crate::{
+        tests::{capture_with, TEST_CALLSITE, TEST_META},
+        EventFormatter, RewriteLayer,
+    };
+
+    // neither an event nor a span
+    static HINT_META: Metadata<'static> = tracing_core::metadata! {
+        name: "hint",
+        target: module_path!(),
+        level: tracing::Level::INFO,
+        fields: &[],
+        callsite: &TEST_CALLSITE,
+        kind: tracing_core::Kind::HINT,
+    };
+
+    #[test]
+    fn unknown_kinds_are_events() {
+        let metadata = super::build(&HINT_META, &crate::Rewrite::default(), Default::default());
+        assert!(metadata.is_event());
+        assert!(!metadata.is_span());
+    }
+
+    #[test]
+    fn cloned_fields_are_equal() {
+        let fields = super::clone_fields(&TEST_META);
+        assert_eq!(
+            fields.iter().map(|field| field.name()).collect::<Vec<_>>(),
+            ["first", "second"]
+        );
+        // fields of the original callsite must be accepted by the cloned set
+        let first = TEST_META.fields().field("first").unwrap();
+        assert!(fields.contains(&first));
+        assert_eq!(fields.field("first"), Some(first.clone()));
+        let values = [(&first, Some(&1 as &dyn Value))];
+        assert_eq!(fields.value_set(&values).len(), 1);
+    }
+
+    // callsite and level of the events reaching the layer
+    #[derive(Clone, Default)]
+    struct Callsites(Arc<Mutex<Vec<(Identifier, Level)>>>);
+
+    impl<S: Subscriber> Layer<S> for Callsites {
+        fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+            let metadata = event.metadata();
+            self.0
+                .lock()
+                .unwrap()
+                .push((metadata.callsite(), *metadata.level()));
+        }
+    }
+
+    // tests leaking on purpose don't run alongside the ones counting leaks
+    static LEAKING: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn leaks_are_counted() {
+        let _leaking = LEAKING.lock().unwrap_or_else(|e| e.into_inner());
+        let before = super::leaked_metadata_count();
+        let subscriber = Registry::default()
+            .with(RewriteLayer::<10, _, _>::new(Callsites::default(), |_| {
+                Some(Level::WARN)
+            }));
+        tracing::subscriber::with_default(subscriber, || {
+            for _ in 0..3 {
+                tracing::error!("test");
+            }
+        });
+
+        // other tests run in parallel, leaking too
+        let leaked = super::leaked_metadata_count() - before;
+        if cfg!(feature = "cache_metadata") {
+            assert!(leaked >= 1);
+        } else if cfg!(feature = "i_really_want_memory_leak") && !cfg!(feature = "arena") {
+            assert!(leaked >= 3);
+        } else {
+            assert_eq!(leaked, 0);
+        }
+    }
+
+    #[test]
+    fn strategies() {
+        let _leaking = LEAKING.lock().unwrap_or_else(|e| e.into_inner());
+        for (strategy, leaks) in [
+            (MetadataStrategy::Free, 0),
+            (MetadataStrategy::Leak, 3),
+            (MetadataStrategy::Cache, 1),
+        ] {
+            let formatter = EventFormatter::builder()
+                .formatter(fmt::format().compact().without_time())
+                .check(|metadata| (*metadata.level() == Level::ERROR).then_some(Level::WARN))
+                .metadata_strategy(strategy)
+                .build();
+
+            let before = super::leaked_metadata_count();
+            let output = capture_with(DefaultFields::new(), formatter, || {
+                for i in 0..3 {
+                    tracing::error!(i, "test");
+                }
+            });
+
+            assert_eq!(
+                output,
+                " WARN tracing_rewrite::metadata::tests: test i=0\n WARN \
+                 tracing_rewrite::metadata::tests: test i=1\n WARN \
+                 tracing_rewrite::metadata::tests: test i=2\n",
+                "{strategy:?}"
+            );
+            // with features leaking by default, other tests leak too
+            let leaked = super::leaked_metadata_count() - before;
+            if MetadataStrategy::default() == MetadataStrategy::Free {
+                assert_eq!(leaked, leaks, "{strategy:?}");
+            } else {
+                assert!(leaked >= leaks, "{strategy:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn callsite_is_preserved() {
+        let (original, rewritten) = (Callsites::default(), Callsites::default());
+        let subscriber =
+            Registry::default()
+                .with(original.clone())
+                .with(RewriteLayer::<10, _, _>::new(rewritten.clone(), |_| {
+                    Some(crate::Rewrite {
+                        level: Some(Level::WARN),
+                        target: Some("other"),
+                        original_level: true,
+                        ..Default::default()
+                    })
+                }));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::error!("first");
+            tracing::error!(a = 1, "second");
+        });
+
+        // the rewritten metadata is new, but keeps the callsite of the original event,
+        // also when fields are appended to it
+        let original = original.0.lock().unwrap();
+        let rewritten = rewritten.0.lock().unwrap();
+        assert_eq!(original.len(), 2);
+        assert_eq!(rewritten.len(), 2);
+        assert_ne!(original[0].0, original[1].0);
+        for ((original, from), (rewritten, to)) in original.iter().zip(rewritten.iter()) {
+            assert_eq!(original, rewritten);
+            assert_eq!((*from, *to), (Level::ERROR, Level::WARN));
+        }
+    }
+
+    #[cfg(feature = "cache_metadata")]
+    #[derive(Clone, Default)]
+    struct Addresses(Arc<Mutex<Vec<usize>>>);
+
+    #[cfg(feature = "cache_metadata")]
+    impl<S: Subscriber> Layer<S> for Addresses {
+        fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+            let address = event.metadata() as *const Metadata<'static> as usize;
+            self.0.lock().unwrap().push(address);
+        }
+    }
+
+    #[cfg(feature = "cache_metadata")]
+    #[test]
+    fn cached_per_callsite_and_rewrite() {
+        let addresses = Addresses::default();
+        let subscriber = Registry::default().with(RewriteLayer::<10, _, _>::new(
+            addresses.clone(),
+            |metadata| {
+                if metadata.fields().field("other").is_some() {
+                    Some(Level::INFO)
+                } else {
+                    Some(Level::WARN)
+                }
+            },
+        ));
+
+        tracing::subscriber::with_default(subscriber, || {
+            for _ in 0..3 {
+                tracing::error!("test");
+            }
+            tracing::error!(other = 1, "test");
+        });
+
+        let addresses = addresses.0.lock().unwrap();
+        assert_eq!(addresses[0], addresses[1]);
+        assert_eq!(addresses[1], addresses[2]);
+        assert_ne!(addresses[2], addresses[3]);
+    }
+}
@@ -0,0 +1,110 @@
+use std::{cell::RefCell, rc::Rc};
+
+use tracing::Metadata;
+
+use crate::Decision;
+
+/// Check set for the current thread only, see [`set_thread_policy`]
+pub type ThreadPolicy = dyn Fn(&Metadata<'static>) -> Decision;
+
+thread_local! {
+    static POLICY: RefCell<Option<Rc<ThreadPolicy>>> = const { RefCell::new(None) };
+}
+
+/// Sets the check used for the events formatted on the current thread, replacing any previous one,
+/// e.g. to rewrite the events of background workers differently from the ones of request threads.
+///
+/// The thread policy wins over the check of every [`EventFormatter`](crate::EventFormatter) and
+/// [`DynEventFormatter`](crate::DynEventFormatter), that is called only on threads without one.
+/// Span lifecycle events still go to [`check_spans`](crate::EventFormatterBuilder::check_spans)
+/// when set, and a disabled [`RewriteHandle`](crate::RewriteHandle) disables thread policies too.
+///
+/// The fmt layer formats events on the thread emitting them, so the policy applies to the events
+/// emitted on this thread. It doesn't need to be `Send` or `Sync`, and it can set another
+/// policy or clear itself while running.
+///
+/// ```rust
+/// use tracing::Level;
+/// use tracing_rewrite::{clear_thread_policy, set_thread_policy};
+///
+/// std::thread::spawn(|| {
+///     // this worker is chatty, its warnings aren't worth an alert
+///     set_thread_policy(|metadata| (*metadata.level() == Level::WARN).then_some(Level::INFO));
+///     tracing::warn!("retrying");
+///     clear_thread_policy();
+/// });
+/// ```
+pub fn set_thread_policy<T, R>(check: T)
+where
+    T: Fn(&Metadata<'static>) -> R + 'static,
+    R: Into<Decision>,
+{
+    let policy: Rc<ThreadPolicy> = Rc::new(move |metadata| check(metadata).into());
+    POLICY.with(|current| *current.borrow_mut() = Some(policy));
+}
+
+/// Removes the policy of the current thread, its events go back to the check of the formatter
+pub fn clear_thread_policy() {
+    POLICY.with(|current| current.borrow_mut().take());
+}
+
+// the decision of the thread policy, `None` when the thread doesn't have one
+pub(crate) fn thread_policy(metadata: &Metadata<'static>) -> Option<Decision> {
+    // the policy runs without the cell borrowed, so that it can replace itself,
+    // threads being torn down behave as if they didn't have one
+    let policy = POLICY.try_with(|current| current.borrow().clone()).ok()??;
+    Some(policy(metadata))
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::Level;
+    use tracing_subscriber::fmt::{self, format::DefaultFields};
+
+    use super::{clear_thread_policy, set_thread_policy};
+    use crate::{tests::capture_with, EventFormatter};
+
+    #[test]
+    fn isolation() {
+        let formatter =
+            EventFormatter::<10, _, _>::new(fmt::format().compact().without_time(), |_| {
+                Some(Level::ERROR)
+            });
+
+        let output = capture_with(DefaultFields::new(), formatter, || {
+            let dispatch = tracing::dispatcher::get_default(Clone::clone);
+            std::thread::scope(|scope| {
+                for name in ["worker", "request"] {
+                    let dispatch = dispatch.clone();
+                    scope.spawn(move || {
+                        if name == "worker" {
+                            set_thread_policy(|_| Some(Level::TRACE));
+                        }
+                        tracing::dispatcher::with_default(&dispatch, || tracing::warn!("{name}"));
+                    });
+                }
+            });
+
+            tracing::warn!("main");
+            set_thread_policy(|_| None::<Level>);
+            tracing::warn!("kept");
+            clear_thread_policy();
+            tracing::warn!("cleared");
+        });
+
+        let mut lines = output.lines().collect::<Vec<_>>();
+        // the spawned threads run in any order
+        lines[..2].sort_unstable();
+        assert_eq!(
+            lines,
+            [
+                "ERROR tracing_rewrite::policy::tests: request",
+                "TRACE tracing_rewrite::policy::tests: worker",
+                "ERROR tracing_rewrite::policy::tests: main",
+                " WARN tracing_rewrite::policy::tests: kept",
+                "ERROR tracing_rewrite::policy::tests: cleared",
+            ],
+            "{output}"
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use tracing::{Level, Metadata};
+
+use crate::remap::matches_target;
+
+/// Condition on the metadata of an event, composed with [`and`](Predicate::and) and
+/// [`or`](Predicate::or) and turned into a check with [`then_level`](Predicate::then_level).
+///
+/// Combinators are plain structs, so composing predicates doesn't allocate, closures taking
+/// the metadata and returning a `bool` are predicates too.
+///
+/// ```rust
+/// use tracing::Level;
+/// use tracing_rewrite::{at_level, in_file, target_prefix, EventFormatter, Predicate};
+/// use tracing_subscriber::fmt;
+///
+/// let formatter = EventFormatter::<10, _, _>::new(
+///     fmt::format(),
+///     target_prefix("hyper")
+///         .and(at_level(Level::DEBUG))
+///         .or(in_file("src/net.rs"))
+///         .then_level(Level::WARN),
+/// );
+/// ```
+pub trait Predicate {
+    /// Whether the event described by `metadata` matches
+    fn matches(&self, metadata: &Metadata<'_>) -> bool;
+
+    /// Matches the events matched by both predicates, `other` is evaluated only if `self` matches
+    fn and<P: Predicate>(self, other: P) -> And<Self, P>
+    where
+        Self: Sized,
+    {
+        And(self, other)
+    }
+
+    /// Matches the events matched by either predicate, `other` is evaluated only if `self` doesn't match
+    fn or<P: Predicate>(self, other: P) -> Or<Self, P>
+    where
+        Self: Sized,
+    {
+        Or(self, other)
+    }
+
+    /// Builds the check rewriting the matched events to `level`
+    fn then_level(self, level: Level) -> impl Fn(&Metadata<'static>) -> Option<Level> + Send + Sync
+    where
+        Self: Sized + Send + Sync,
+    {
+        move |metadata| self.matches(metadata).then_some(level)
+    }
+}
+
+impl<F: Fn(&Metadata<'_>) -> bool> Predicate for F {
+    fn matches(&self, metadata: &Metadata<'_>) -> bool {
+        self(metadata)
+    }
+}
+
+/// See [`Predicate::and`]
+#[derive(Clone, Copy, Debug)]
+pub struct And<A, B>(A, B);
+
+impl<A: Predicate, B: Predicate> Predicate for And<A, B> {
+    fn matches(&self, metadata: &Metadata<'_>) -> bool {
+        self.0.matches(metadata) && self.1.matches(metadata)
+    }
+}
+
+/// See [`Predicate::or`]
+#[derive(Clone, Copy, Debug)]
+pub struct Or<A, B>(A, B);
+
+impl<A: Predicate, B: Predicate> Predicate for Or<A, B> {
+    fn matches(&self, metadata: &Metadata<'_>) -> bool {
+        self.0.matches(metadata) || self.1.matches(metadata)
+    }
+}
+
+/// See [`target_prefix`]
+#[derive(Clone, Copy, Debug)]
+pub struct TargetPrefix(&'static str);
+
+impl Predicate for TargetPrefix {
+    fn matches(&self, metadata: &Metadata<'_>) -> bool {
+        matches_target(metadata.target(), self.0)
+    }
+}
+
+/// Matches the events whose target starts with `prefix`, on module boundaries like [`remap`](crate::remap):
+/// `"hyper"` matches `hyper` and `hyper::proto` but not `hyper_util`
+pub fn target_prefix(prefix: &'static str) -> TargetPrefix {
+    TargetPrefix(prefix)
+}
+
+/// See [`at_level`]
+#[derive(Clone, Copy, Debug)]
+pub struct AtLevel(Level);
+
+impl Predicate for AtLevel {
+    fn matches(&self, metadata: &Metadata<'_>) -> bool {
+        *metadata.level() == self.0
+    }
+}
+
+/// Matches the events of exactly `level`
+pub fn at_level(level: Level) -> AtLevel {
+    AtLevel(level)
+}
+
+/// See [`in_file`]
+#[derive(Clone, Copy, Debug)]
+pub struct InFile(&'static str);
+
+impl Predicate for InFile {
+    fn matches(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.file().is_some_and(|file| {
+            file.strip_suffix(self.0)
+                .is_some_and(|rest| rest.is_empty() || rest.ends_with('/'))
+        })
+    }
+}
+
+/// Matches the events emitted in `path`, compared on path components from the end,
+/// so that `"src/net.rs"` matches `crates/server/src/net.rs` too, in a workspace
+pub fn in_file(path: &'static str) -> InFile {
+    InFile(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::{field::FieldSet, metadata::Kind, Level, Metadata};
+    use tracing_subscriber::fmt::{self, format::DefaultFields};
+
+    use super::{at_level, in_file, target_prefix, Predicate};
+    use crate::tests::{capture, TEST_META};
+
+    #[test]
+    fn combinators() {
+        let output = capture(
+            DefaultFields::new(),
+            fmt::format().compact().without_time(),
+            target_prefix("hyper")
+                .and(at_level(Level::DEBUG))
+                .or(in_file("predicate.rs")
+                    .and(|metadata: &Metadata<'_>| metadata.fields().field("promote").is_some()))
+                .then_level(Level::WARN),
+            || {
+                tracing::debug!(target: "hyper::proto", "promoted");
+                tracing::info!(target: "hyper::proto", "other level");
+                tracing::debug!(target: "hyper_util", "other target");
+                tracing::info!(promote = true, "same file");
+                tracing::info!("not promoted");
+            },
+        );
+
+        assert_eq!(
+            output,
+            " WARN hyper::proto: promoted\n INFO hyper::proto: other level\nDEBUG hyper_util: other \
+             target\n WARN tracing_rewrite::predicate::tests: same file promote=true\n INFO \
+             tracing_rewrite::predicate::tests: not promoted\n"
+        );
+    }
+
+    #[test]
+    fn file_components() {
+        let meta = |file| {
+            let fields = FieldSet::new(&[], TEST_META.callsite());
+            Metadata::new(
+                "test",
+                "test",
+                Level::INFO,
+                Some(file),
+                None,
+                None,
+                fields,
+                Kind::EVENT,
+            )
+        };
+
+        let file = in_file("src/net.rs");
+        assert!(file.matches(&meta("src/net.rs")));
+        assert!(file.matches(&meta("crates/server/src/net.rs")));
+        assert!(!file.matches(&meta("crates/server/mysrc/net.rs")));
+        assert!(!file.matches(&meta("src/net.rs.bak")));
+    }
+}
@@ -0,0 +1,120 @@
+use std::fmt::{self, Debug};
+
+use tracing::{
+    field::{Field, Visit},
+    Event, Level, Metadata, Value,
+};
+use tracing_core::{metadata, Callsite, Interest, Kind};
+
+use crate::{visitor::Visitor, with_rewritten, PooledText, Rewrite, ORIGINAL_LEVEL_FIELD_NAME};
+
+/// Error returned by [`EventFormatter::try_new`](crate::EventFormatter::try_new)
+/// when rewritten events can't be built correctly with the linked `tracing` version
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RewriteError {
+    /// The rewritten metadata doesn't have the expected fields
+    Fields,
+    /// The rewritten event doesn't have the expected level or values
+    Event,
+}
+
+impl fmt::Display for RewriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RewriteError::Fields => f.write_str("rewritten metadata has unexpected fields"),
+            RewriteError::Event => f.write_str("rewritten event has unexpected level or values"),
+        }
+    }
+}
+
+impl std::error::Error for RewriteError {}
+
+struct ProbeCallsite;
+static PROBE_CALLSITE: ProbeCallsite = ProbeCallsite;
+static PROBE_META: Metadata<'static> = metadata! {
+    name: "probe",
+    target: module_path!(),
+    level: Level::INFO,
+    fields: &["probe"],
+    callsite: &PROBE_CALLSITE,
+    kind: Kind::EVENT,
+};
+
+impl Callsite for ProbeCallsite {
+    fn set_interest(&self, _: Interest) {}
+
+    fn metadata(&self) -> &Metadata<'_> {
+        &PROBE_META
+    }
+}
+
+// collects the values of the rewritten event, formatted the same way whatever their type
+struct Values(Vec<String>);
+
+impl Visit for Values {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0.push(format!("{}={value:?}", field.name()));
+    }
+}
+
+/// Rewrites a synthetic event, reporting whether the result is what the original event would look like
+pub(crate) fn probe() -> Result<(), RewriteError> {
+    let field = PROBE_META
+        .fields()
+        .field("probe")
+        .ok_or(RewriteError::Fields)?;
+    let values = [(&field, Some(&42 as &dyn Value))];
+    let valueset = PROBE_META.fields().value_set(&values);
+    let event = Event::new(&PROBE_META, &valueset);
+
+    let mut visitor = Visitor::<2, PooledText>::new(event.fields().count());
+    event.record(&mut visitor);
+
+    let rewrite = Rewrite {
+        level: Some(Level::WARN),
+        original_level: true,
+        ..Default::default()
+    };
+    with_rewritten(
+        &event,
+        &rewrite,
+        visitor,
+        None,
+        Default::default(),
+        Default::default(),
+        |event| {
+            let metadata = event.metadata();
+            let names = metadata.fields().iter().map(|field| field.name());
+            if !names.eq(["probe", ORIGINAL_LEVEL_FIELD_NAME])
+                || !metadata.fields().contains(&field)
+            {
+                return Err(RewriteError::Fields);
+            }
+
+            let mut values = Values(Vec::new());
+            event.record(&mut values);
+            if *metadata.level() != Level::WARN
+                || values.0 != ["probe=42", "original_level=\"INFO\""]
+            {
+                return Err(RewriteError::Event);
+            }
+
+            Ok(())
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::Level;
+    use tracing_subscriber::fmt;
+
+    use super::probe;
+    use crate::EventFormatter;
+
+    #[test]
+    fn try_new() {
+        assert_eq!(probe(), Ok(()));
+        assert!(EventFormatter::<10, _, _>::try_new(fmt::format(), |_| Some(Level::WARN)).is_ok());
+    }
+}
@@ -0,0 +1,487 @@
+use std::{env, fmt, str::FromStr};
+
+use tracing::{Level, Metadata};
+
+use crate::EventFormatter;
+
+/// Level matching every level in a [`remap`] rule
+pub const ANY_LEVEL: Option<Level> = None;
+
+/// Builds a check out of `(target, from, to)` rules: events whose target matches `target`
+/// and whose level is `from` get level `to`, the first matching rule wins.
+///
+/// `target` is a prefix matched on module boundaries, so `"hyper"` matches `hyper` and `hyper::proto`
+/// but not `hyper_util`, an empty `target` matches everything.
+/// Use [`ANY_LEVEL`] as `from` to match every level.
+pub fn remap<const N: usize>(
+    rules: [(&'static str, Option<Level>, Level); N],
+) -> impl Fn(&Metadata<'static>) -> Option<Level> + Send + Sync {
+    move |metadata| {
+        rules
+            .iter()
+            .find_map(|(target, from, to)| matches(metadata, target, *from).then_some(*to))
+    }
+}
+
+/// Builds a check capping the severity of the events whose target matches `target`,
+/// matched the same way as in [`remap`]: events more severe than `max` get level `max`,
+/// the others are left untouched.
+///
+/// E.g. with `max` as `WARN`, `ERROR` becomes `WARN` while `WARN`, `INFO` and below don't change.
+pub fn clamp_max(
+    target: &'static str,
+    max: Level,
+) -> impl Fn(&Metadata<'static>) -> Option<Level> + Send + Sync {
+    // `Level` orders by verbosity, `ERROR` is the smallest one
+    move |metadata| {
+        (matches_target(metadata.target(), target) && *metadata.level() < max).then_some(max)
+    }
+}
+
+/// Builds a [`remap`] check out of a table of rules, the first matching rule wins.
+///
+/// `from` and `to` are [`Level`] names, `from: ANY` matches every level.
+///
+/// ```rust
+/// use tracing_rewrite::{rewrite_rules, EventFormatter};
+/// use tracing_subscriber::fmt;
+///
+/// let formatter = EventFormatter::<10, _, _>::new(
+///     fmt::format(),
+///     rewrite_rules! {
+///         { target: "hyper", from: DEBUG, to: WARN },
+///         { target: "sqlx::query", from: INFO, to: TRACE },
+///         { target: "noisy", from: ANY, to: TRACE },
+///     },
+/// );
+/// ```
+#[macro_export]
+macro_rules! rewrite_rules {
+    ($({ target: $target:expr, from: $from:ident, to: $to:ident $(,)? }),* $(,)?) => {
+        $crate::remap([$(
+            (
+                $target,
+                $crate::__rewrite_rules_level!($from),
+                $crate::__private::Level::$to,
+            )
+        ),*])
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __rewrite_rules_level {
+    (ANY) => {
+        $crate::ANY_LEVEL
+    };
+    ($level:ident) => {
+        ::core::option::Option::Some($crate::__private::Level::$level)
+    };
+}
+
+/// Like [`remap`], with `target` a regular expression searched in the target of the events,
+/// e.g. `^aws_sdk_` matches `aws_sdk_s3` while `sdk` matches any target containing it.
+///
+/// Patterns are compiled once, here, returning the error of the first invalid one.
+#[cfg(feature = "regex")]
+pub fn remap_regex(
+    rules: &[(&str, Option<Level>, Level)],
+) -> Result<impl Fn(&Metadata<'static>) -> Option<Level> + Send + Sync, regex::Error> {
+    let rules = rules
+        .iter()
+        .map(|(pattern, from, to)| Ok((regex::Regex::new(pattern)?, *from, *to)))
+        .collect::<Result<Vec<_>, regex::Error>>()?;
+    Ok(move |metadata: &Metadata<'static>| {
+        rules.iter().find_map(|(target, from, to)| {
+            (target.is_match(metadata.target())
+                && from.is_none_or(|from| from == *metadata.level()))
+            .then_some(*to)
+        })
+    })
+}
+
+fn matches(metadata: &Metadata<'_>, target: &str, from: Option<Level>) -> bool {
+    matches_target(metadata.target(), target) && from.is_none_or(|from| from == *metadata.level())
+}
+
+pub(crate) fn matches_target(target: &str, prefix: &str) -> bool {
+    match target.strip_prefix(prefix) {
+        Some(rest) => prefix.is_empty() || rest.is_empty() || rest.starts_with("::"),
+        None => false,
+    }
+}
+
+impl<const VISITOR_SIZE: usize, F> EventFormatter<VISITOR_SIZE, F, ()> {
+    /// Like [`EventFormatter::new`], with the check built by [`remap`] out of `rules`
+    pub fn remap<const N: usize>(
+        formatter: F,
+        rules: [(&'static str, Option<Level>, Level); N],
+    ) -> EventFormatter<VISITOR_SIZE, F, impl Fn(&Metadata<'static>) -> Option<Level> + Send + Sync>
+    {
+        EventFormatter::new(formatter, remap(rules))
+    }
+
+    /// Like [`EventFormatter::new`], with the check built by [`clamp_max`]
+    pub fn clamp_max(
+        formatter: F,
+        target: &'static str,
+        max: Level,
+    ) -> EventFormatter<VISITOR_SIZE, F, impl Fn(&Metadata<'static>) -> Option<Level> + Send + Sync>
+    {
+        EventFormatter::new(formatter, clamp_max(target, max))
+    }
+
+    /// Like [`EventFormatter::new`], with the check built by [`remap_regex`] out of `rules`
+    #[cfg(feature = "regex")]
+    pub fn remap_regex(
+        formatter: F,
+        rules: &[(&str, Option<Level>, Level)],
+    ) -> Result<
+        EventFormatter<VISITOR_SIZE, F, impl Fn(&Metadata<'static>) -> Option<Level> + Send + Sync>,
+        regex::Error,
+    > {
+        Ok(EventFormatter::new(formatter, remap_regex(rules)?))
+    }
+}
+
+/// [`remap`] rules parsed at runtime, e.g. from an environment variable with [`RemapRules::from_env`].
+///
+/// Rules are separated by commas, every rule is `target=from->to`:
+/// * `target` is matched the same way as in [`remap`], without `target=` the rule matches every target
+/// * `from` is a level, or `*` to match every level
+/// * `to` is a level
+///
+/// Levels are `trace`, `debug`, `info`, `warn` and `error`, in any case,
+/// e.g. `dep_crate=debug->warn,other::module=info->error,*->info`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RemapRules(Vec<(String, Option<Level>, Level)>);
+
+/// Error parsing [`RemapRules`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RemapError {
+    /// The environment variable is not set or is not valid unicode
+    Env(env::VarError),
+    /// The rule is not `target=from->to`
+    InvalidRule(String),
+    /// The level is not one of `trace`, `debug`, `info`, `warn`, `error` or `*`
+    InvalidLevel(String),
+}
+
+impl fmt::Display for RemapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RemapError::Env(e) => write!(f, "can't read rewrite rules: {e}"),
+            RemapError::InvalidRule(rule) => {
+                write!(
+                    f,
+                    "invalid rewrite rule `{rule}`, expected `target=from->to`"
+                )
+            }
+            RemapError::InvalidLevel(level) => write!(f, "invalid level `{level}`"),
+        }
+    }
+}
+
+impl std::error::Error for RemapError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RemapError::Env(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl RemapRules {
+    /// Parses the rules in the environment variable `var`
+    pub fn from_env(var: &str) -> Result<Self, RemapError> {
+        env::var(var).map_err(RemapError::Env)?.parse()
+    }
+
+    /// Builds a check out of the rules, the first matching rule wins
+    pub fn check(self) -> impl Fn(&Metadata<'static>) -> Option<Level> + Send + Sync {
+        move |metadata| {
+            self.0
+                .iter()
+                .find_map(|(target, from, to)| matches(metadata, target, *from).then_some(*to))
+        }
+    }
+}
+
+fn parse_level(level: &str) -> Result<Level, RemapError> {
+    level
+        .parse()
+        .map_err(|_| RemapError::InvalidLevel(level.to_owned()))
+}
+
+impl FromStr for RemapRules {
+    type Err = RemapError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|rule| !rule.is_empty())
+            .map(|rule| {
+                let (target, levels) = rule.split_once('=').unwrap_or(("", rule));
+                let (from, to) = levels
+                    .split_once("->")
+                    .ok_or_else(|| RemapError::InvalidRule(rule.to_owned()))?;
+                let (target, from, to) = (target.trim(), from.trim(), to.trim());
+                if target.contains(char::is_whitespace) {
+                    return Err(RemapError::InvalidRule(rule.to_owned()));
+                }
+
+                let from = match from {
+                    "*" => ANY_LEVEL,
+                    from => Some(parse_level(from)?),
+                };
+                Ok((target.to_owned(), from, parse_level(to)?))
+            })
+            .collect::<Result<_, _>>()
+            .map(RemapRules)
+    }
+}
+
+impl fmt::Display for RemapRules {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (target, from, to)) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            if !target.is_empty() {
+                write!(f, "{target}=")?;
+            }
+            match from {
+                Some(from) => write!(f, "{}", from.as_str().to_lowercase())?,
+                None => f.write_str("*")?,
+            }
+            write!(f, "->{}", to.as_str().to_lowercase())?;
+        }
+        Ok(())
+    }
+}
+
+impl<const VISITOR_SIZE: usize, F> EventFormatter<VISITOR_SIZE, F, ()> {
+    /// Like [`EventFormatter::new`], with the check built out of the [`RemapRules`] in the
+    /// environment variable `var`, so that the rewrite policy can change at deploy time
+    pub fn from_env(
+        formatter: F,
+        var: &str,
+    ) -> Result<
+        EventFormatter<VISITOR_SIZE, F, impl Fn(&Metadata<'static>) -> Option<Level> + Send + Sync>,
+        RemapError,
+    > {
+        Ok(EventFormatter::new(
+            formatter,
+            RemapRules::from_env(var)?.check(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::VarError;
+
+    use tracing::Level;
+    use tracing_subscriber::fmt::{self, format::DefaultFields};
+
+    use super::{matches_target, RemapError, RemapRules, ANY_LEVEL};
+    use crate::{
+        tests::{capture_with, levels},
+        EventFormatter,
+    };
+
+    #[test]
+    fn parse_rules() {
+        let rules: RemapRules = " dep_crate=debug->warn, other::module = INFO -> error,*->trace,"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            rules,
+            RemapRules(vec![
+                ("dep_crate".to_owned(), Some(Level::DEBUG), Level::WARN),
+                ("other::module".to_owned(), Some(Level::INFO), Level::ERROR),
+                (String::new(), ANY_LEVEL, Level::TRACE),
+            ])
+        );
+
+        let canonical = "dep_crate=debug->warn,other::module=info->error,*->trace";
+        assert_eq!(rules.to_string(), canonical);
+        assert_eq!(canonical.parse::<RemapRules>().unwrap(), rules);
+        assert_eq!("".parse::<RemapRules>().unwrap(), RemapRules::default());
+    }
+
+    #[test]
+    fn invalid_rules() {
+        let parse = |rules: &str| rules.parse::<RemapRules>().unwrap_err();
+
+        assert_eq!(
+            parse("a=warn->error,b=warn"),
+            RemapError::InvalidRule("b=warn".to_owned())
+        );
+        assert_eq!(
+            parse("a b=warn->error"),
+            RemapError::InvalidRule("a b=warn->error".to_owned())
+        );
+        assert_eq!(
+            parse("a=warning->error"),
+            RemapError::InvalidLevel("warning".to_owned())
+        );
+        assert_eq!(parse("a=warn->"), RemapError::InvalidLevel(String::new()));
+        assert_eq!(
+            RemapRules::from_env("TRACING_REWRITE_TEST_MISSING").unwrap_err(),
+            RemapError::Env(VarError::NotPresent)
+        );
+    }
+
+    #[test]
+    fn rules_check() {
+        let rules: RemapRules = "tracing_rewrite::remap=error->warn,tracing_rewrite=*->trace"
+            .parse()
+            .unwrap();
+        let output = capture_with(
+            DefaultFields::new(),
+            EventFormatter::<10, _, _>::new(fmt::format().compact().without_time(), rules.check()),
+            || {
+                tracing::error!("error");
+                tracing::info!("info");
+                tracing::error!(target: "other", "other");
+            },
+        );
+
+        assert_eq!(
+            output,
+            " WARN tracing_rewrite::remap::tests: error\nTRACE tracing_rewrite::remap::tests: \
+             info\nERROR other: other\n"
+        );
+    }
+
+    #[test]
+    fn rewrite_rules() {
+        let check = crate::rewrite_rules! {
+            { target: "hyper", from: DEBUG, to: WARN },
+            { target: "sqlx::query", from: ANY, to: TRACE, },
+        };
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+        assert_send_sync(&check);
+
+        let output = capture_with(
+            DefaultFields::new(),
+            EventFormatter::<10, _, _>::new(fmt::format().compact().without_time(), check),
+            || {
+                tracing::debug!(target: "hyper::proto", "promoted");
+                tracing::info!(target: "hyper", "untouched");
+                tracing::error!(target: "sqlx::query", "demoted");
+                tracing::error!(target: "sqlx::query_builder", "other");
+            },
+        );
+
+        assert_eq!(
+            output,
+            " WARN hyper::proto: promoted\n INFO hyper: untouched\nTRACE sqlx::query: demoted\n\
+             ERROR sqlx::query_builder: other\n"
+        );
+    }
+
+    #[test]
+    fn clamp_max() {
+        let clamped = |max| {
+            capture_with(
+                DefaultFields::new(),
+                EventFormatter::<10, _, _>::clamp_max(
+                    fmt::format().compact().without_time(),
+                    "tracing_rewrite",
+                    max,
+                ),
+                || {
+                    tracing::error!("error");
+                    tracing::warn!("warn");
+                    tracing::info!("info");
+                    tracing::trace!("trace");
+                    tracing::error!(target: "other", "other");
+                },
+            )
+        };
+
+        assert_eq!(
+            levels(&clamped(Level::WARN)),
+            ["WARN", "WARN", "INFO", "TRACE", "ERROR"]
+        );
+        assert_eq!(
+            levels(&clamped(Level::INFO)),
+            ["INFO", "INFO", "INFO", "TRACE", "ERROR"]
+        );
+        assert_eq!(
+            levels(&clamped(Level::ERROR)),
+            ["ERROR", "WARN", "INFO", "TRACE", "ERROR"]
+        );
+        assert_eq!(
+            levels(&clamped(Level::TRACE)),
+            ["TRACE", "TRACE", "TRACE", "TRACE", "ERROR"]
+        );
+    }
+
+    #[test]
+    fn target_prefix() {
+        assert!(matches_target("hyper", "hyper"));
+        assert!(matches_target("hyper::proto::h1", "hyper"));
+        assert!(!matches_target("hyper_util", "hyper"));
+        assert!(!matches_target("tokio", "hyper"));
+        assert!(matches_target("tokio", ""));
+    }
+
+    #[test]
+    fn remap() {
+        let output = capture_with(
+            DefaultFields::new(),
+            EventFormatter::<10, _, _>::remap(
+                fmt::format().compact().without_time(),
+                [
+                    ("tracing_rewrite::remap", Some(Level::ERROR), Level::WARN),
+                    ("tracing_rewrite", ANY_LEVEL, Level::TRACE),
+                ],
+            ),
+            || {
+                tracing::error!("error");
+                tracing::info!("info");
+                tracing::error!(target: "other", "other");
+            },
+        );
+
+        assert_eq!(
+            output,
+            " WARN tracing_rewrite::remap::tests: error\nTRACE tracing_rewrite::remap::tests: \
+             info\nERROR other: other\n"
+        );
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn remap_regex() {
+        let output = capture_with(
+            DefaultFields::new(),
+            EventFormatter::<10, _, _>::remap_regex(
+                fmt::format().compact().without_time(),
+                &[
+                    ("^aws_sdk_.*", Some(Level::DEBUG), Level::WARN),
+                    ("cache", ANY_LEVEL, Level::TRACE),
+                ],
+            )
+            .unwrap(),
+            || {
+                tracing::debug!(target: "aws_sdk_s3", "anchored");
+                tracing::debug!(target: "my_aws_sdk_s3", "not at the start");
+                tracing::info!(target: "aws_sdk_s3", "other level");
+                tracing::error!(target: "app::cache::lru", "unanchored");
+                tracing::error!(target: "other", "other");
+            },
+        );
+
+        assert_eq!(
+            output,
+            " WARN aws_sdk_s3: anchored\nDEBUG my_aws_sdk_s3: not at the start\n INFO aws_sdk_s3: \
+             other level\nTRACE app::cache::lru: unanchored\nERROR other: other\n"
+        );
+
+        assert!(super::remap_regex(&[("(", ANY_LEVEL, Level::WARN)]).is_err());
+    }
+}
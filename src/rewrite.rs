@@ -0,0 +1,305 @@
+use tracing::Level;
+
+/// Kind a rewritten event is reported as, see [`Rewrite::kind`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MetadataKind {
+    Event,
+    Span,
+}
+
+/// Describes how a matching event gets rewritten, every `None` keeps the original value.
+///
+/// `Metadata` lives for `'static`, so every replacement string must be `'static` too:
+/// use string literals, or intern runtime strings once at startup
+/// (e.g. with `Box::leak(string.into_boxed_str())`) and reuse them from the `check` closure.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Rewrite {
+    /// replacement level
+    pub level: Option<Level>,
+    /// replacement target, useful to re-route events to targets matched by your filters
+    pub target: Option<&'static str>,
+    /// replacement name, useful to group several callsites under a single name
+    pub name: Option<&'static str>,
+    /// replacement module path
+    pub module_path: Option<&'static str>,
+    /// replacement file, useful to point to your code rather than to a dependency
+    pub file: Option<&'static str>,
+    /// replacement line
+    pub line: Option<u32>,
+    /// replacement kind, e.g. to let the inner formatter treat some events as spans
+    pub kind: Option<MetadataKind>,
+    /// text written before the formatted event, e.g. `[ESCALATED] ` to flag an event keeping its level,
+    /// ignored by [`RewriteLayer`](crate::RewriteLayer)
+    pub prefix: Option<&'static str>,
+    /// text written at the end of the formatted event, before the newline,
+    /// ignored by [`RewriteLayer`](crate::RewriteLayer)
+    pub suffix: Option<&'static str>,
+    /// when `true` the event gets an [`ORIGINAL_LEVEL_FIELD_NAME`](crate::ORIGINAL_LEVEL_FIELD_NAME) field
+    /// holding its level before the rewrite, useful for auditing
+    pub original_level: bool,
+    /// when `true` the event isn't written at all, every other rewrite is ignored
+    pub drop: bool,
+}
+
+impl Rewrite {
+    /// Suppresses the event, useful when the decision depends on something your filters can't see
+    pub const DROP: Rewrite = Rewrite {
+        level: None,
+        target: None,
+        name: None,
+        module_path: None,
+        file: None,
+        line: None,
+        kind: None,
+        prefix: None,
+        suffix: None,
+        original_level: false,
+        drop: true,
+    };
+}
+
+impl From<Level> for Rewrite {
+    fn from(level: Level) -> Self {
+        Rewrite {
+            level: Some(level),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tracing::Level;
+    use tracing_subscriber::fmt::{
+        self,
+        format::{DefaultFields, JsonFields},
+    };
+
+    use super::{MetadataKind, Rewrite};
+    use crate::{
+        tests::{capture, capture_with, MetadataFormatter},
+        EventFormatter,
+    };
+
+    #[test]
+    fn target() {
+        let output = capture(
+            fmt::format::DefaultFields::new(),
+            fmt::format().compact().without_time(),
+            |metadata| {
+                metadata
+                    .target()
+                    .starts_with("tracing_rewrite")
+                    .then_some(Rewrite {
+                        target: Some("my_app::noisy"),
+                        ..Default::default()
+                    })
+            },
+            || tracing::error!(a = 1, "test"),
+        );
+
+        assert!(
+            output.contains("ERROR my_app::noisy: test a=1\n"),
+            "{output}"
+        );
+    }
+
+    #[test]
+    fn level_and_target() {
+        let output = capture(
+            fmt::format::DefaultFields::new(),
+            fmt::format().compact().without_time(),
+            |_| {
+                Some(Rewrite {
+                    target: Some("my_app::noisy"),
+                    ..Rewrite::from(Level::DEBUG)
+                })
+            },
+            || tracing::error!("test"),
+        );
+
+        assert!(output.contains("DEBUG my_app::noisy: test\n"), "{output}");
+    }
+
+    #[test]
+    fn name() {
+        let emit = || tracing::error!("test");
+        let output = capture_with(
+            DefaultFields::new(),
+            EventFormatter::<10, _, _>::new(MetadataFormatter, |_| {
+                Some(Rewrite {
+                    name: Some("canonical"),
+                    ..Default::default()
+                })
+            }),
+            emit,
+        );
+
+        assert!(output.starts_with("canonical "), "{output}");
+
+        let output = capture_with(
+            DefaultFields::new(),
+            EventFormatter::<10, _, _>::new(MetadataFormatter, |_| Some(Level::WARN)),
+            emit,
+        );
+        let original = capture_with(
+            DefaultFields::new(),
+            EventFormatter::<10, _, _>::new(MetadataFormatter, |_| None::<Level>),
+            emit,
+        );
+
+        assert_eq!(
+            output.split_once(' ').unwrap().0,
+            original.split_once(' ').unwrap().0
+        );
+    }
+
+    #[test]
+    fn location() {
+        let emit = || tracing::debug!(a = 1, "test");
+        let output = capture(
+            JsonFields::new(),
+            fmt::format()
+                .json()
+                .without_time()
+                .with_file(true)
+                .with_line_number(true),
+            |_| {
+                Some(Rewrite {
+                    file: Some("src/my_app.rs"),
+                    line: Some(42),
+                    ..Rewrite::from(Level::WARN)
+                })
+            },
+            emit,
+        );
+
+        assert!(output.contains(r#""level":"WARN""#), "{output}");
+        assert!(output.contains(r#""filename":"src/my_app.rs""#), "{output}");
+        assert!(output.contains(r#""line_number":42"#), "{output}");
+
+        // untouched fields are the same of the original event
+        let output = capture_with(
+            DefaultFields::new(),
+            EventFormatter::<10, _, _>::new(MetadataFormatter, |_| {
+                Some(Rewrite {
+                    module_path: Some("my_app"),
+                    ..Default::default()
+                })
+            }),
+            emit,
+        );
+        let original = capture_with(
+            DefaultFields::new(),
+            EventFormatter::<10, _, _>::new(MetadataFormatter, |_| None::<Level>),
+            emit,
+        );
+
+        assert!(output.ends_with(" Some(\"my_app\")\n"), "{output}");
+        assert_eq!(
+            output.rsplit_once(' ').unwrap().0,
+            original.rsplit_once(' ').unwrap().0
+        );
+    }
+
+    #[test]
+    fn drop() {
+        let output = capture(
+            DefaultFields::new(),
+            fmt::format().compact().without_time(),
+            |metadata| (*metadata.level() == Level::DEBUG).then_some(Rewrite::DROP),
+            || {
+                tracing::debug!("dropped");
+                tracing::info!("kept");
+            },
+        );
+
+        assert!(!output.contains("dropped"), "{output}");
+        assert!(
+            output.contains("INFO tracing_rewrite::rewrite::tests: kept\n"),
+            "{output}"
+        );
+    }
+
+    #[test]
+    fn kind() {
+        let kinds = Arc::new(Mutex::new(Vec::new()));
+        let recorded = kinds.clone();
+        // the inner formatter only records the kind it receives
+        let inner = EventFormatter::<10, _, _>::new(
+            fmt::format().compact().without_time(),
+            move |metadata| {
+                recorded
+                    .lock()
+                    .unwrap()
+                    .push((metadata.is_event(), metadata.is_span()));
+                None::<Level>
+            },
+        );
+        capture(
+            DefaultFields::new(),
+            inner,
+            |metadata| {
+                metadata
+                    .fields()
+                    .field("span")
+                    .is_some()
+                    .then_some(Rewrite {
+                        kind: Some(MetadataKind::Span),
+                        ..Default::default()
+                    })
+            },
+            || {
+                tracing::info!(span = true, "reclassified");
+                tracing::info!("kept");
+            },
+        );
+
+        assert_eq!(*kinds.lock().unwrap(), [(false, true), (true, false)]);
+    }
+
+    #[test]
+    fn original_level() {
+        let output = capture(
+            DefaultFields::new(),
+            fmt::format().compact().without_time(),
+            |_| {
+                Some(Rewrite {
+                    level: Some(Level::WARN),
+                    original_level: true,
+                    ..Default::default()
+                })
+            },
+            || tracing::debug!(a = 1, "test"),
+        );
+
+        assert_eq!(
+            output,
+            " WARN tracing_rewrite::rewrite::tests: test a=1 original_level=\"DEBUG\"\n"
+        );
+    }
+
+    #[test]
+    fn original_level_of_truncated_events() {
+        // the original level takes the place of the last field
+        let output = capture_with(
+            DefaultFields::new(),
+            EventFormatter::<3, _, _>::new(fmt::format().compact().without_time(), |_| {
+                Some(Rewrite {
+                    level: Some(Level::WARN),
+                    original_level: true,
+                    ..Default::default()
+                })
+            }),
+            || tracing::debug!(a = 1, b = 2, "test"),
+        );
+
+        assert_eq!(
+            output,
+            " WARN tracing_rewrite::rewrite::tests: test fields_truncated=2 \
+             original_level=\"DEBUG\"\n"
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use std::{fmt, sync::Arc};
+
+use tracing::{Event, Subscriber};
+use tracing_subscriber::{
+    fmt::{format::Writer, FmtContext, FormatEvent, FormatFields},
+    registry::LookupSpan,
+};
+
+use crate::EventFormatter;
+
+/// Cheaply cloneable formatter, sharing a single [`EventFormatter`] (or any other formatter)
+/// between several subscribers, see [`EventFormatter::shared`].
+///
+/// Everything lives behind the same `Arc`, so rate limits, sampling counters and the
+/// [`RewriteHandle`](crate::RewriteHandle) are shared as well.
+pub struct SharedEventFormatter<E>(Arc<E>);
+
+impl<E> SharedEventFormatter<E> {
+    pub fn new(formatter: E) -> Self {
+        SharedEventFormatter(Arc::new(formatter))
+    }
+}
+
+impl<E> Clone for SharedEventFormatter<E> {
+    fn clone(&self) -> Self {
+        SharedEventFormatter(Arc::clone(&self.0))
+    }
+}
+
+impl<E> From<Arc<E>> for SharedEventFormatter<E> {
+    fn from(formatter: Arc<E>) -> Self {
+        SharedEventFormatter(formatter)
+    }
+}
+
+impl<E> std::ops::Deref for SharedEventFormatter<E> {
+    type Target = E;
+
+    fn deref(&self) -> &E {
+        &self.0
+    }
+}
+
+impl<const VISITOR_SIZE: usize, F, T, B> EventFormatter<VISITOR_SIZE, F, T, B> {
+    /// Moves the formatter behind an `Arc`, so that clones of it can be given to several subscribers
+    pub fn shared(self) -> SharedEventFormatter<Self> {
+        SharedEventFormatter::new(self)
+    }
+}
+
+impl<E, S, N> FormatEvent<S, N> for SharedEventFormatter<E>
+where
+    E: FormatEvent<S, N>,
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        self.0.format_event(ctx, writer, event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::{Level, Metadata};
+    use tracing_subscriber::fmt::{self, format::DefaultFields};
+
+    use super::SharedEventFormatter;
+    use crate::{tests::capture_with, DefaultEventFormatter, Rewrite};
+
+    #[test]
+    fn shared() {
+        let formatter = DefaultEventFormatter::<_, ()>::remap(
+            fmt::format().compact().without_time(),
+            [("tracing_rewrite", Some(Level::ERROR), Level::WARN)],
+        )
+        .shared();
+        let handle = formatter.handle();
+
+        let log = || tracing::error!("test");
+        let first = capture_with(DefaultFields::new(), formatter.clone(), log);
+        let second = capture_with(DefaultFields::new(), formatter.clone(), log);
+        assert_eq!(first, " WARN tracing_rewrite::shared::tests: test\n");
+        assert_eq!(second, first);
+
+        // one switch for every subscriber
+        handle.disable();
+        let third = capture_with(DefaultFields::new(), formatter, log);
+        assert_eq!(third, "ERROR tracing_rewrite::shared::tests: test\n");
+    }
+
+    #[test]
+    fn send_sync() {
+        fn assert_send_sync<T: Send + Sync + Clone>() {}
+
+        type Check = fn(&Metadata<'static>) -> Option<Rewrite>;
+        assert_send_sync::<SharedEventFormatter<DefaultEventFormatter<fmt::format::Format, Check>>>(
+        );
+    }
+}
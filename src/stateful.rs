@@ -0,0 +1,68 @@
+use std::sync::Mutex;
+
+use tracing::Metadata;
+
+/// Turns a `FnMut` check into a check usable by [`EventFormatter`](crate::EventFormatter),
+/// so that it can keep its own state without atomics.
+///
+/// Events come from many threads, so every call locks a `Mutex`: under heavy logging the threads
+/// contend for it, while a `Fn` check using atomics doesn't serialize them.
+///
+/// ```rust
+/// use std::collections::HashMap;
+///
+/// use tracing::{callsite::Identifier, Level};
+/// use tracing_rewrite::{stateful, EventFormatter};
+/// use tracing_subscriber::fmt;
+///
+/// // promotes the events of a callsite to ERROR from the 5th occurrence on
+/// let mut seen = HashMap::<Identifier, u32>::new();
+/// let formatter = EventFormatter::<10, _, _>::new(
+///     fmt::format(),
+///     stateful(move |metadata| {
+///         let count = seen.entry(metadata.callsite()).or_default();
+///         *count += 1;
+///         (*count >= 5).then_some(Level::ERROR)
+///     }),
+/// );
+/// ```
+pub fn stateful<T, R>(check: T) -> impl Fn(&Metadata<'static>) -> Option<R> + Send + Sync
+where
+    T: FnMut(&Metadata<'static>) -> Option<R> + Send,
+{
+    let check = Mutex::new(check);
+    move |metadata| (check.lock().unwrap_or_else(|e| e.into_inner()))(metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::Level;
+    use tracing_subscriber::fmt::{self, format::DefaultFields};
+
+    use super::stateful;
+    use crate::{
+        tests::{capture_with, levels},
+        EventFormatter,
+    };
+
+    #[test]
+    fn counter() {
+        let mut count = 0;
+        let formatter = EventFormatter::<10, _, _>::new(
+            fmt::format().compact().without_time(),
+            stateful(move |_| {
+                count += 1;
+                (count > 2).then_some(Level::ERROR)
+            }),
+        );
+
+        let output = capture_with(DefaultFields::new(), formatter, || {
+            for _ in 0..4 {
+                tracing::warn!("test");
+            }
+        });
+
+        let levels = levels(&output);
+        assert_eq!(levels, ["WARN", "WARN", "ERROR", "ERROR"]);
+    }
+}
@@ -0,0 +1,407 @@
+use std::path::PathBuf;
+
+use valuable::{
+    EnumDef, Enumerable, Fields, Listable, Mappable, NamedField, NamedValues, Slice, StructDef,
+    Structable, Tuplable, TupleDef, Valuable, Value, Variant, VariantDef, Visit,
+};
+
+use crate::visitor::CapturedError;
+
+/// Owned copy of a `valuable::Value`, handed back to the formatter as a `Value` with the same
+/// structure: structs stay structs, lists stay lists, and so on, instead of being formatted through `Debug`.
+///
+/// Named types are copied as dynamic ones, since their definition can't be borrowed from the event,
+/// their names and fields are the same though.
+pub enum Owned {
+    Bool(bool),
+    Char(char),
+    F32(f32),
+    F64(f64),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    I128(i128),
+    Isize(isize),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    U128(u128),
+    Usize(usize),
+    Unit,
+    String(String),
+    Path(PathBuf),
+    Error(CapturedError),
+    List(List),
+    Map(Map),
+    Struct(Struct),
+    Enum(Enum),
+    Tuple(Tuple),
+}
+
+impl Owned {
+    pub fn new(value: Value<'_>) -> Self {
+        match value {
+            Value::Bool(v) => Owned::Bool(v),
+            Value::Char(v) => Owned::Char(v),
+            Value::F32(v) => Owned::F32(v),
+            Value::F64(v) => Owned::F64(v),
+            Value::I8(v) => Owned::I8(v),
+            Value::I16(v) => Owned::I16(v),
+            Value::I32(v) => Owned::I32(v),
+            Value::I64(v) => Owned::I64(v),
+            Value::I128(v) => Owned::I128(v),
+            Value::Isize(v) => Owned::Isize(v),
+            Value::U8(v) => Owned::U8(v),
+            Value::U16(v) => Owned::U16(v),
+            Value::U32(v) => Owned::U32(v),
+            Value::U64(v) => Owned::U64(v),
+            Value::U128(v) => Owned::U128(v),
+            Value::Usize(v) => Owned::Usize(v),
+            Value::Unit => Owned::Unit,
+            Value::String(v) => Owned::String(v.to_owned()),
+            Value::Path(v) => Owned::Path(v.to_owned()),
+            Value::Error(v) => Owned::Error(CapturedError::new(v)),
+            Value::Listable(v) => Owned::List(List(Collector::of(v).values)),
+            Value::Mappable(v) => Owned::Map(Map(Collector::of(v).entries)),
+            Value::Structable(v) => {
+                let definition = v.definition();
+                Owned::Struct(Struct {
+                    name: definition.name().to_owned(),
+                    fields: Collector::of(v).fields(definition.fields()),
+                })
+            }
+            Value::Enumerable(v) => {
+                let variant = v.variant();
+                Owned::Enum(Enum {
+                    name: v.definition().name().to_owned(),
+                    variant: variant.name().to_owned(),
+                    fields: Collector::of(v).fields(variant.fields()),
+                })
+            }
+            Value::Tuplable(v) => Owned::Tuple(Tuple(Collector::of(v).values)),
+            // variants added after this crate was written
+            v => Owned::String(format!("{v:?}")),
+        }
+    }
+}
+
+impl Valuable for Owned {
+    fn as_value(&self) -> Value<'_> {
+        match self {
+            Owned::Bool(v) => Value::Bool(*v),
+            Owned::Char(v) => Value::Char(*v),
+            Owned::F32(v) => Value::F32(*v),
+            Owned::F64(v) => Value::F64(*v),
+            Owned::I8(v) => Value::I8(*v),
+            Owned::I16(v) => Value::I16(*v),
+            Owned::I32(v) => Value::I32(*v),
+            Owned::I64(v) => Value::I64(*v),
+            Owned::I128(v) => Value::I128(*v),
+            Owned::Isize(v) => Value::Isize(*v),
+            Owned::U8(v) => Value::U8(*v),
+            Owned::U16(v) => Value::U16(*v),
+            Owned::U32(v) => Value::U32(*v),
+            Owned::U64(v) => Value::U64(*v),
+            Owned::U128(v) => Value::U128(*v),
+            Owned::Usize(v) => Value::Usize(*v),
+            Owned::Unit => Value::Unit,
+            Owned::String(v) => Value::String(v),
+            Owned::Path(v) => Value::Path(v),
+            Owned::Error(v) => Value::Error(v),
+            Owned::List(v) => Value::Listable(v),
+            Owned::Map(v) => Value::Mappable(v),
+            Owned::Struct(v) => Value::Structable(v),
+            Owned::Enum(v) => Value::Enumerable(v),
+            Owned::Tuple(v) => Value::Tuplable(v),
+        }
+    }
+
+    fn visit(&self, visit: &mut dyn Visit) {
+        visit.visit_value(self.as_value());
+    }
+}
+
+pub enum OwnedFields {
+    Named(Vec<(String, Owned)>),
+    Unnamed(Vec<Owned>),
+}
+
+impl OwnedFields {
+    // dynamic definitions don't list their fields, they're only known while visiting
+    fn definition(&self) -> Fields<'_> {
+        match self {
+            OwnedFields::Named(_) => Fields::Named(&[]),
+            OwnedFields::Unnamed(values) => Fields::Unnamed(values.len()),
+        }
+    }
+
+    fn visit(&self, visit: &mut dyn Visit) {
+        match self {
+            OwnedFields::Named(fields) => {
+                let names = fields
+                    .iter()
+                    .map(|(name, _)| NamedField::new(name))
+                    .collect::<Vec<_>>();
+                let values = fields
+                    .iter()
+                    .map(|(_, value)| value.as_value())
+                    .collect::<Vec<_>>();
+                visit.visit_named_fields(&NamedValues::new(&names, &values));
+            }
+            OwnedFields::Unnamed(values) => {
+                let values = values.iter().map(Owned::as_value).collect::<Vec<_>>();
+                visit.visit_unnamed_fields(&values);
+            }
+        }
+    }
+}
+
+pub struct List(Vec<Owned>);
+
+impl Valuable for List {
+    fn as_value(&self) -> Value<'_> {
+        Value::Listable(self)
+    }
+
+    fn visit(&self, visit: &mut dyn Visit) {
+        for value in &self.0 {
+            visit.visit_value(value.as_value());
+        }
+    }
+}
+
+impl Listable for List {
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.len(), Some(self.0.len()))
+    }
+}
+
+pub struct Map(Vec<(Owned, Owned)>);
+
+impl Valuable for Map {
+    fn as_value(&self) -> Value<'_> {
+        Value::Mappable(self)
+    }
+
+    fn visit(&self, visit: &mut dyn Visit) {
+        for (key, value) in &self.0 {
+            visit.visit_entry(key.as_value(), value.as_value());
+        }
+    }
+}
+
+impl Mappable for Map {
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.len(), Some(self.0.len()))
+    }
+}
+
+pub struct Struct {
+    name: String,
+    fields: OwnedFields,
+}
+
+impl Valuable for Struct {
+    fn as_value(&self) -> Value<'_> {
+        Value::Structable(self)
+    }
+
+    fn visit(&self, visit: &mut dyn Visit) {
+        self.fields.visit(visit);
+    }
+}
+
+impl Structable for Struct {
+    fn definition(&self) -> StructDef<'_> {
+        StructDef::new_dynamic(&self.name, self.fields.definition())
+    }
+}
+
+pub struct Enum {
+    name: String,
+    variant: String,
+    fields: OwnedFields,
+}
+
+impl Valuable for Enum {
+    fn as_value(&self) -> Value<'_> {
+        Value::Enumerable(self)
+    }
+
+    fn visit(&self, visit: &mut dyn Visit) {
+        self.fields.visit(visit);
+    }
+}
+
+impl Enumerable for Enum {
+    fn definition(&self) -> EnumDef<'_> {
+        EnumDef::new_dynamic(&self.name, &[])
+    }
+
+    fn variant(&self) -> Variant<'_> {
+        Variant::Dynamic(VariantDef::new(&self.variant, self.fields.definition()))
+    }
+}
+
+pub struct Tuple(Vec<Owned>);
+
+impl Valuable for Tuple {
+    fn as_value(&self) -> Value<'_> {
+        Value::Tuplable(self)
+    }
+
+    fn visit(&self, visit: &mut dyn Visit) {
+        let values = self.0.iter().map(Owned::as_value).collect::<Vec<_>>();
+        visit.visit_unnamed_fields(&values);
+    }
+}
+
+impl Tuplable for Tuple {
+    fn definition(&self) -> TupleDef {
+        TupleDef::new_static(self.0.len())
+    }
+}
+
+// copies whatever a value hands to its visitor
+#[derive(Default)]
+struct Collector {
+    values: Vec<Owned>,
+    named: Vec<(String, Owned)>,
+    entries: Vec<(Owned, Owned)>,
+}
+
+impl Collector {
+    fn of(value: &dyn Valuable) -> Self {
+        let mut collector = Collector::default();
+        value.visit(&mut collector);
+        collector
+    }
+
+    fn fields(self, definition: &Fields<'_>) -> OwnedFields {
+        match definition {
+            Fields::Named(_) => OwnedFields::Named(self.named),
+            Fields::Unnamed(_) => OwnedFields::Unnamed(self.values),
+        }
+    }
+}
+
+impl Visit for Collector {
+    fn visit_value(&mut self, value: Value<'_>) {
+        self.values.push(Owned::new(value));
+    }
+
+    fn visit_named_fields(&mut self, named_values: &NamedValues<'_>) {
+        for (field, value) in named_values {
+            self.named
+                .push((field.name().to_owned(), Owned::new(*value)));
+        }
+    }
+
+    fn visit_unnamed_fields(&mut self, values: &[Value<'_>]) {
+        self.values.extend(values.iter().copied().map(Owned::new));
+    }
+
+    fn visit_primitive_slice(&mut self, slice: Slice<'_>) {
+        self.values.extend(slice.iter().map(Owned::new));
+    }
+
+    fn visit_entry(&mut self, key: Value<'_>, value: Value<'_>) {
+        self.entries.push((Owned::new(key), Owned::new(value)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt;
+
+    use tracing::{
+        field::{Field, Visit},
+        Level,
+    };
+    use tracing_subscriber::{
+        field::RecordFields,
+        fmt::{self as tracing_fmt, format::Writer, FormatFields},
+    };
+    use valuable::{Valuable, Value};
+
+    use crate::tests::capture;
+
+    #[derive(Valuable)]
+    struct User {
+        name: &'static str,
+        roles: Vec<Role>,
+        address: (&'static str, u16),
+    }
+
+    #[derive(Valuable)]
+    enum Role {
+        Admin,
+        Member { team: &'static str },
+    }
+
+    // writes how every value has been recorded, structured values with their kind
+    struct Kinds;
+
+    impl<'w> FormatFields<'w> for Kinds {
+        fn format_fields<R: RecordFields>(&self, writer: Writer<'w>, fields: R) -> fmt::Result {
+            let mut visitor = KindsVisitor {
+                writer,
+                result: Ok(()),
+            };
+            fields.record(&mut visitor);
+            visitor.result
+        }
+    }
+
+    struct KindsVisitor<'w> {
+        writer: Writer<'w>,
+        result: fmt::Result,
+    }
+
+    impl Visit for KindsVisitor<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.result = write!(self.writer, "{}={value:?} ", field.name());
+        }
+
+        fn record_value(&mut self, field: &Field, value: Value<'_>) {
+            let kind = match value {
+                Value::Structable(_) => "structable",
+                _ => "other",
+            };
+            self.result = write!(self.writer, "{}={kind}:{value:?} ", field.name());
+        }
+    }
+
+    #[test]
+    fn structured_values() {
+        let user = User {
+            name: "ferris",
+            roles: vec![Role::Admin, Role::Member { team: "crabs" }],
+            address: ("somewhere", 42),
+        };
+
+        let output = capture(
+            Kinds,
+            tracing_fmt::format().compact().without_time(),
+            |metadata| (*metadata.level() == Level::WARN).then_some(Level::ERROR),
+            || {
+                tracing::warn!(user = tracing::field::valuable(&user), "rewritten");
+                tracing::info!(user = tracing::field::valuable(&user), "untouched");
+            },
+        );
+
+        // the same structure on both events, with `Debug` it would be a string on the rewritten one
+        let fields =
+            "user=structable:User { name: \"ferris\", roles: [Role::Admin, Role::Member { \
+                      team: \"crabs\" }], address: (\"somewhere\", 42) }";
+        assert_eq!(
+            output,
+            format!(
+                "ERROR tracing_rewrite::structured::tests: message=rewritten {fields} \n INFO \
+                 tracing_rewrite::structured::tests: message=untouched {fields} \n"
+            )
+        );
+    }
+}
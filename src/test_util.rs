@@ -0,0 +1,128 @@
+use std::{
+    io,
+    sync::{Arc, Mutex},
+};
+
+use tracing::Level;
+use tracing_subscriber::{
+    fmt::{self, format::DefaultFields, FormatEvent, FormatFields, MakeWriter},
+    Registry,
+};
+
+use crate::EventFormatter;
+
+impl<const VISITOR_SIZE: usize, F, T, B> EventFormatter<VISITOR_SIZE, F, T, B> {
+    /// Formats the events emitted by `f` into a `String`, one line per event, to unit test checks
+    /// without setting up a subscriber.
+    ///
+    /// The subscriber is the default one of the current thread only while `f` runs, every level
+    /// is enabled and ANSI colors are off.
+    /// Fields are formatted with [`DefaultFields`], see [`format_to_string_with`](Self::format_to_string_with)
+    /// for other field formatters, like the JSON one.
+    ///
+    /// ```rust
+    /// use tracing::Level;
+    /// use tracing_rewrite::EventFormatter;
+    /// use tracing_subscriber::fmt;
+    ///
+    /// let formatter = EventFormatter::<10, _, _>::new(fmt::format().compact().without_time(), |_| {
+    ///     Some(Level::WARN)
+    /// });
+    /// let output = formatter.format_to_string(|| tracing::error!(target: "dep", "failed"));
+    /// assert!(output.starts_with(" WARN dep: failed"));
+    /// ```
+    pub fn format_to_string(self, f: impl FnOnce()) -> String
+    where
+        Self: FormatEvent<Registry, DefaultFields> + Send + Sync + 'static,
+    {
+        self.format_to_string_with(DefaultFields::new(), f)
+    }
+
+    /// Like [`format_to_string`](Self::format_to_string), with fields formatted by `fields`
+    pub fn format_to_string_with<N>(self, fields: N, f: impl FnOnce()) -> String
+    where
+        Self: FormatEvent<Registry, N> + Send + Sync + 'static,
+        N: for<'a> FormatFields<'a> + Send + Sync + 'static,
+    {
+        let buffer = Buffer::default();
+        let subscriber = fmt::Subscriber::builder()
+            .with_max_level(Level::TRACE)
+            .with_ansi(false)
+            .with_writer(buffer.clone())
+            .fmt_fields(fields)
+            .event_format(self)
+            .finish();
+        tracing::subscriber::with_default(subscriber, f);
+
+        let output = buffer.0.lock().unwrap_or_else(|e| e.into_inner());
+        String::from_utf8_lossy(&output).into_owned()
+    }
+}
+
+#[derive(Clone, Default)]
+struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut buffer = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for Buffer {
+    type Writer = Self;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::Level;
+    use tracing_subscriber::fmt::{self, format::JsonFields};
+
+    use crate::{remap, tests::untraced, EventFormatter};
+
+    #[test]
+    fn format_to_string() {
+        let formatter = EventFormatter::<10, _, _>::new(
+            fmt::format().compact().without_time(),
+            remap([("dep", Some(Level::ERROR), Level::WARN)]),
+        );
+
+        let output = untraced(formatter.format_to_string(|| {
+            tracing::error!(target: "dep", a = 1, "rewritten");
+            tracing::error!(target: "other", "untouched");
+            tracing::trace!(target: "dep", "every level is enabled");
+        }));
+
+        assert_eq!(
+            output,
+            " WARN dep: rewritten a=1\nERROR other: untouched\nTRACE dep: every level is enabled\n"
+        );
+    }
+
+    #[test]
+    fn format_to_string_with() {
+        let formatter = EventFormatter::<10, _, _>::new(
+            fmt::format().json().without_time().flatten_event(true),
+            |_| Some(Level::WARN),
+        );
+
+        let output = formatter.format_to_string_with(
+            JsonFields::new(),
+            || tracing::error!(target: "dep", a = 1, "rewritten"),
+        );
+
+        assert_eq!(
+            output,
+            "{\"level\":\"WARN\",\"message\":\"rewritten\",\"a\":1,\"target\":\"dep\"}\n"
+        );
+    }
+}
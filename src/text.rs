@@ -0,0 +1,219 @@
+use std::{
+    cell::RefCell,
+    fmt::{self, Write},
+    ops::Range,
+};
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Where the visitor formats string and `Debug` values, see [`PooledText`] and [`StackText`]
+pub trait TextBuffer: sealed::Sealed + Write + Default {
+    fn as_str(&self) -> &str;
+}
+
+// appended to the values cut by `EventFormatterBuilder::max_field_len`
+const TRUNCATION_MARKER: &str = "…";
+
+// appends `args` to `text`, returning the range it has been written to
+pub(crate) fn write_text<B: TextBuffer>(text: &mut B, args: fmt::Arguments<'_>) -> Range<usize> {
+    let start = text.as_str().len();
+    // writing to a buffer never fails, only a misbehaving Debug implementation can
+    let _ = text.write_fmt(args);
+    start..text.as_str().len()
+}
+
+// like `write_text`, but with `max_len` the text is cut at the last character boundary
+// within `max_len` bytes, followed by `TRUNCATION_MARKER`
+pub(crate) fn write_text_within<B: TextBuffer>(
+    text: &mut B,
+    args: fmt::Arguments<'_>,
+    max_len: Option<usize>,
+) -> Range<usize> {
+    let Some(max_len) = max_len else {
+        return write_text(text, args);
+    };
+
+    let start = text.as_str().len();
+    let mut limited = Limited {
+        text,
+        remaining: max_len,
+        cut: false,
+    };
+    // the error returned once the limit is hit stops the formatting of the rest of the value
+    let _ = limited.write_fmt(args);
+    if limited.cut {
+        let _ = text.write_str(TRUNCATION_MARKER);
+    }
+    start..text.as_str().len()
+}
+
+struct Limited<'a, B> {
+    text: &'a mut B,
+    remaining: usize,
+    cut: bool,
+}
+
+impl<B: Write> Write for Limited<'_, B> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if s.len() <= self.remaining {
+            self.remaining -= s.len();
+            return self.text.write_str(s);
+        }
+
+        let mut end = self.remaining;
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.text.write_str(&s[..end])?;
+        self.remaining = 0;
+        self.cut = true;
+        Err(fmt::Error)
+    }
+}
+
+thread_local! {
+    // text buffers are reused across events, so that steady-state recording doesn't allocate
+    static BUFFERS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Default text buffer, a `String` taken from a thread-local pool and given back on drop,
+/// so that it allocates only while the pool warms up
+pub struct PooledText(String);
+
+impl Default for PooledText {
+    fn default() -> Self {
+        let buffer = BUFFERS
+            .try_with(|buffers| buffers.borrow_mut().pop())
+            .ok()
+            .flatten();
+        PooledText(buffer.unwrap_or_default())
+    }
+}
+
+impl Drop for PooledText {
+    fn drop(&mut self) {
+        let mut buffer = std::mem::take(&mut self.0);
+        buffer.clear();
+        let _ = BUFFERS.try_with(|buffers| {
+            let mut buffers = buffers.borrow_mut();
+            // nested visitors are rare, there's no reason to keep many buffers around
+            if buffers.len() < 4 {
+                buffers.push(buffer);
+            }
+        });
+    }
+}
+
+impl Write for PooledText {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.push_str(s);
+        Ok(())
+    }
+}
+
+impl sealed::Sealed for PooledText {}
+
+impl TextBuffer for PooledText {
+    fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Text buffer of `CAP` bytes living on the stack, it never allocates.
+///
+/// Only text goes into the buffer: recorded errors and byte slices are still copied to the heap.
+///
+/// Values are written as long as they fit: the first value that doesn't fit is cut at the last
+/// character boundary that does, and every value after it is recorded as an empty string.
+pub struct StackText<const CAP: usize> {
+    buf: [u8; CAP],
+    len: usize,
+    full: bool,
+}
+
+impl<const CAP: usize> Default for StackText<CAP> {
+    fn default() -> Self {
+        StackText {
+            buf: [0; CAP],
+            len: 0,
+            full: false,
+        }
+    }
+}
+
+impl<const CAP: usize> Write for StackText<CAP> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.full {
+            return Ok(());
+        }
+
+        let mut end = s.len().min(CAP - self.len);
+        if end < s.len() {
+            self.full = true;
+            while !s.is_char_boundary(end) {
+                end -= 1;
+            }
+        }
+
+        self.buf[self.len..self.len + end].copy_from_slice(&s.as_bytes()[..end]);
+        self.len += end;
+        Ok(())
+    }
+}
+
+impl<const CAP: usize> sealed::Sealed for StackText<CAP> {}
+
+impl<const CAP: usize> TextBuffer for StackText<CAP> {
+    fn as_str(&self) -> &str {
+        // only whole characters are ever written
+        std::str::from_utf8(&self.buf[..self.len]).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Write;
+
+    use tracing::Level;
+    use tracing_subscriber::fmt::{self, format::DefaultFields};
+
+    use super::{StackText, TextBuffer};
+    use crate::{tests::capture_with, EventFormatter};
+
+    #[test]
+    fn stack_text_is_cut_on_char_boundaries() {
+        let mut text = StackText::<5>::default();
+        write!(text, "abc").unwrap();
+        // `è` takes 2 bytes, only 1 is left after `d`
+        write!(text, "dè").unwrap();
+        // once full, nothing else gets in, even if it would fit
+        write!(text, "f").unwrap();
+        assert_eq!(text.as_str(), "abcd");
+    }
+
+    #[test]
+    fn stack_text() {
+        let formatter = EventFormatter::builder()
+            .formatter(fmt::format().compact().without_time())
+            .check(|_| Some(Level::WARN))
+            .text_buffer::<StackText<12>>()
+            .build();
+
+        let output = capture_with(DefaultFields::new(), formatter, || {
+            tracing::error!(
+                number = 1,
+                a = "fits",
+                b = "doesn't fit",
+                c = "lost",
+                "message"
+            );
+        });
+
+        assert_eq!(
+            output,
+            " WARN tracing_rewrite::text::tests: message number=1 a=\"fits\" b=\"d\" c=\"\"\n"
+        );
+    }
+}
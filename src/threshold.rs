@@ -0,0 +1,87 @@
+use tracing::{Level, Metadata};
+
+use crate::{EventFormatter, FieldSnapshot, FieldValue, WithFields};
+
+/// Builds a check, for [`EventFormatter::with_fields`], giving level `level` to the events
+/// whose field `name` is an integer greater than or equal to `threshold`, whatever their level,
+/// e.g. promoting to `ERROR` the events with a `status` of 500 or more.
+///
+/// Integers are compared whatever type they have been recorded with, strings and `Debug` values
+/// are parsed as integers. Events without the field, or with a value that isn't an integer,
+/// are left untouched.
+pub fn field_ge(
+    name: &'static str,
+    threshold: i64,
+    level: Level,
+) -> impl Fn(&Metadata<'static>, &FieldSnapshot<'_>) -> Option<Level> + Send + Sync {
+    move |_, fields| {
+        let value = fields.get(name)?;
+        (integer(&value)? >= i128::from(threshold)).then_some(level)
+    }
+}
+
+// `i128` holds both `i64` and `u64` values, so a huge `u64` still compares as greater
+fn integer(value: &FieldValue<'_>) -> Option<i128> {
+    match *value {
+        FieldValue::I64(v) => Some(v.into()),
+        FieldValue::U64(v) => Some(v.into()),
+        FieldValue::I128(v) => Some(v),
+        FieldValue::U128(v) => v.try_into().ok(),
+        _ => value.as_str()?.trim().parse().ok(),
+    }
+}
+
+impl<const VISITOR_SIZE: usize, F> EventFormatter<VISITOR_SIZE, F, ()> {
+    /// Like [`EventFormatter::with_fields`], with the check built by [`field_ge`]
+    pub fn on_field_ge(
+        formatter: F,
+        name: &'static str,
+        threshold: i64,
+        level: Level,
+    ) -> EventFormatter<
+        VISITOR_SIZE,
+        F,
+        WithFields<impl Fn(&Metadata<'static>, &FieldSnapshot<'_>) -> Option<Level> + Send + Sync>,
+    > {
+        EventFormatter::with_fields(formatter, field_ge(name, threshold, level))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::Level;
+    use tracing_subscriber::fmt::{self, format::DefaultFields};
+
+    use crate::{
+        tests::{capture_with, levels},
+        EventFormatter,
+    };
+
+    #[test]
+    fn on_field_ge() {
+        let formatter = EventFormatter::<10, _, _>::on_field_ge(
+            fmt::format().compact().without_time(),
+            "status",
+            500,
+            Level::ERROR,
+        );
+
+        let output = capture_with(DefaultFields::new(), formatter, || {
+            tracing::info!(status = 499, "below");
+            tracing::info!(status = 500, "boundary");
+            tracing::info!(status = 503u64, "unsigned");
+            tracing::warn!(status = "500", "text");
+            tracing::info!(status = u64::MAX, "huge");
+            tracing::info!(status = "unknown", "not numeric");
+            tracing::info!(status = 5.0e2, "float");
+            tracing::info!(code = 500, "absent");
+        });
+
+        let levels = levels(&output);
+        assert_eq!(
+            levels,
+            ["INFO", "ERROR", "ERROR", "ERROR", "ERROR", "INFO", "INFO", "INFO"],
+            "{output}"
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+use tracing::callsite::Identifier;
+
+/// Limits how many events of every callsite get rewritten in a time window,
+/// see [`EventFormatterBuilder::rate_limit`](crate::EventFormatterBuilder::rate_limit).
+///
+/// Events exceeding the limit are formatted with their original metadata, unless [`RateLimit::drop_exceeding`] is used.
+/// Suppressed events (see [`Rewrite::DROP`](crate::Rewrite::DROP)) don't count towards the limit.
+#[derive(Debug)]
+pub struct RateLimit {
+    count: u32,
+    window: Duration,
+    drop: bool,
+    windows: Mutex<HashMap<Identifier, (Instant, u32)>>,
+}
+
+/// What to do with a rewritten event, according to a [`RateLimit`]
+pub(crate) enum Admission {
+    Rewrite,
+    Original,
+    Drop,
+}
+
+impl RateLimit {
+    /// Rewrites at most `count` events per callsite every `window`
+    pub fn new(count: u32, window: Duration) -> Self {
+        RateLimit {
+            count,
+            window,
+            drop: false,
+            windows: Mutex::default(),
+        }
+    }
+
+    /// Drops the events exceeding the limit, instead of formatting them with their original metadata
+    pub fn drop_exceeding(self) -> Self {
+        RateLimit { drop: true, ..self }
+    }
+
+    pub(crate) fn admit(&self, callsite: Identifier) -> Admission {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+        let (start, count) = windows.entry(callsite).or_insert((now, 0));
+        if now.duration_since(*start) >= self.window {
+            *start = now;
+            *count = 0;
+        }
+
+        if *count < self.count {
+            *count += 1;
+            Admission::Rewrite
+        } else if self.drop {
+            Admission::Drop
+        } else {
+            Admission::Original
+        }
+    }
+}
+
+/// Rewrites only one every `n` matching events of every callsite,
+/// see [`EventFormatterBuilder::sample`](crate::EventFormatterBuilder::sample)
+#[derive(Debug)]
+pub(crate) struct Sampling {
+    n: u64,
+    counters: Counters,
+}
+
+impl Sampling {
+    pub(crate) fn new(n: u32) -> Self {
+        assert!(n > 0, "sampling needs at least 1 event");
+        Sampling {
+            n: n.into(),
+            counters: Counters::default(),
+        }
+    }
+
+    // the first event of every callsite is sampled
+    pub(crate) fn sampled(&self, callsite: Identifier) -> bool {
+        self.counters.next(callsite).is_multiple_of(self.n)
+    }
+}
+
+/// Occurrences of every callsite, counted without locking once the callsite has been seen
+#[derive(Debug, Default)]
+pub(crate) struct Counters(RwLock<HashMap<Identifier, AtomicU64>>);
+
+impl Counters {
+    /// Counts an occurrence of `callsite`, returning the number of the previous ones
+    pub(crate) fn next(&self, callsite: Identifier) -> u64 {
+        let next = |counter: &AtomicU64| counter.fetch_add(1, Ordering::Relaxed);
+
+        if let Some(counter) = self
+            .0
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&callsite)
+        {
+            return next(counter);
+        }
+
+        next(
+            self.0
+                .write()
+                .unwrap_or_else(|e| e.into_inner())
+                .entry(callsite)
+                .or_default(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tracing::Level;
+    use tracing_subscriber::fmt::{self, format::DefaultFields};
+
+    use super::RateLimit;
+    use crate::{
+        tests::{capture_with, levels},
+        EventFormatter,
+    };
+
+    fn rate_limited(limit: RateLimit, f: impl FnOnce()) -> String {
+        capture_with(
+            DefaultFields::new(),
+            EventFormatter::builder()
+                .formatter(fmt::format().compact().without_time())
+                .check(|_| Some(Level::ERROR))
+                .rate_limit(limit)
+                .build(),
+            f,
+        )
+    }
+
+    #[test]
+    fn sampling() {
+        let output = capture_with(
+            DefaultFields::new(),
+            EventFormatter::builder()
+                .formatter(fmt::format().compact().without_time())
+                .check(|_| Some(Level::ERROR))
+                .sample(100)
+                .build(),
+            || {
+                for _ in 0..1000 {
+                    tracing::warn!("test");
+                }
+            },
+        );
+
+        let levels = levels(&output);
+        assert_eq!(levels.len(), 1000);
+        assert_eq!(levels.iter().filter(|level| **level == "ERROR").count(), 10);
+        assert_eq!(levels[0], "ERROR");
+        assert_eq!(levels[100], "ERROR");
+    }
+
+    #[test]
+    fn per_callsite() {
+        let output = rate_limited(RateLimit::new(2, Duration::from_secs(60)), || {
+            for _ in 0..4 {
+                tracing::warn!("first");
+            }
+            tracing::warn!("second");
+        });
+
+        assert_eq!(levels(&output), ["ERROR", "ERROR", "WARN", "WARN", "ERROR"]);
+    }
+
+    #[test]
+    fn drop_exceeding() {
+        let output = rate_limited(
+            RateLimit::new(1, Duration::from_secs(60)).drop_exceeding(),
+            || {
+                for _ in 0..3 {
+                    tracing::warn!("test");
+                }
+            },
+        );
+
+        assert_eq!(levels(&output), ["ERROR"]);
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn window() {
+        let output = rate_limited(RateLimit::new(1, Duration::from_millis(100)), || {
+            let log = || tracing::warn!("test");
+            log();
+            log();
+            std::thread::sleep(Duration::from_millis(150));
+            log();
+        });
+
+        assert_eq!(levels(&output), ["ERROR", "WARN", "ERROR"]);
+    }
+}
@@ -0,0 +1,881 @@
+use std::{
+    error::Error,
+    fmt::{self, Debug, Display},
+    ops::Range,
+};
+
+use smallvec::SmallVec;
+use tracing::{
+    field::{display, DisplayValue, FieldSet, ValueSet, Visit},
+    Event, Level, Metadata, Value,
+};
+use tracing_core::{metadata, Callsite, Field, Interest, Kind};
+
+use crate::{
+    text::{write_text, write_text_within, PooledText, TextBuffer},
+    Transform,
+};
+
+// fields are told apart by callsite and index, never by name, so the placeholder doesn't collide
+// with real fields named the same way
+const FAKE_FIELD_NAME: &str = "foo";
+
+// tracing automatically filters out fields with a different call site
+struct FakeCallSite();
+static FAKE_CALLSITE: FakeCallSite = FakeCallSite();
+static FAKE_META: Metadata<'static> = metadata! {
+    name: "",
+    target: module_path!(),
+    level: Level::INFO,
+    fields: &[FAKE_FIELD_NAME],
+    callsite: &FAKE_CALLSITE,
+    kind: Kind::SPAN,
+};
+
+// placeholder for the unused slots, ignored by `ValueSet` since it belongs to a different callsite
+fn placeholder() -> Field {
+    FAKE_META.fields().field(FAKE_FIELD_NAME).unwrap()
+}
+
+// Safety: same assumptions as before, becuase Field is like
+// ```rust
+// #[derive(Debug)]
+// pub struct Field {
+//     i: usize,
+//     fields: FieldSet,
+// }
+// ```
+#[cfg(not(feature = "safe_fields"))]
+const _: () = assert!(
+    std::mem::size_of::<Field>() == std::mem::size_of::<(usize, FieldSet)>(),
+    "Field layout changed, enable the `safe_fields` feature"
+);
+
+#[cfg(not(feature = "safe_fields"))]
+fn clone_field(field: &Field) -> Field {
+    unsafe { std::mem::transmute_copy::<Field, Field>(field) }
+}
+
+#[cfg(feature = "safe_fields")]
+fn clone_field(field: &Field) -> Field {
+    field.clone()
+}
+
+impl Callsite for FakeCallSite {
+    fn set_interest(&self, _: Interest) {
+        unimplemented!()
+    }
+
+    fn metadata(&self) -> &Metadata<'_> {
+        &FAKE_META
+    }
+}
+
+// owned copy of an error and its chain of sources, so that formatters
+// handling errors specially (e.g. printing `error.sources`) keep working
+#[derive(Debug)]
+pub struct CapturedError {
+    message: String,
+    source: Option<Box<CapturedError>>,
+}
+
+impl CapturedError {
+    pub fn new(error: &(dyn Error + 'static)) -> Self {
+        CapturedError {
+            message: error.to_string(),
+            source: error
+                .source()
+                .map(|source| Box::new(CapturedError::new(source))),
+        }
+    }
+}
+
+impl Display for CapturedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for CapturedError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source
+            .as_deref()
+            .map(|source| source as &(dyn Error + 'static))
+    }
+}
+
+// keeps the original type of the recorded values, so that a downstream formatter
+// sees the same types it would see on the original event
+enum StoredValue {
+    F64(f64),
+    I64(i64),
+    U64(u64),
+    I128(i128),
+    U128(u128),
+    Bool(bool),
+    // range of the visitor text buffer
+    Str(Range<usize>),
+    // bytes aren't text, they can't live in the text buffer
+    Bytes(Box<[u8]>),
+    Error(Box<dyn Error + Send + Sync>),
+    // range of the visitor text buffer, the value has already been formatted: `%` values get here too,
+    // wrapped by tracing so that their `Debug` is their `Display`
+    Debug(Range<usize>),
+    // values recorded through `record_value`, copied with their structure
+    #[cfg(all(tracing_unstable, feature = "valuable"))]
+    Structured(Box<crate::structured::Owned>),
+}
+
+impl StoredValue {
+    fn borrow<'a>(&'a self, text: &'a str) -> BorrowedValue<'a> {
+        match self {
+            StoredValue::F64(v) => BorrowedValue::F64(*v),
+            StoredValue::I64(v) => BorrowedValue::I64(*v),
+            StoredValue::U64(v) => BorrowedValue::U64(*v),
+            StoredValue::I128(v) => BorrowedValue::I128(*v),
+            StoredValue::U128(v) => BorrowedValue::U128(*v),
+            StoredValue::Bool(v) => BorrowedValue::Bool(*v),
+            StoredValue::Str(range) => BorrowedValue::Str(&text[range.clone()]),
+            StoredValue::Bytes(v) => BorrowedValue::Bytes(v),
+            StoredValue::Error(v) => BorrowedValue::Error(v.as_ref()),
+            StoredValue::Debug(range) => BorrowedValue::Debug(display(&text[range.clone()])),
+            #[cfg(all(tracing_unstable, feature = "valuable"))]
+            StoredValue::Structured(v) => {
+                BorrowedValue::Structured(valuable::Valuable::as_value(v.as_ref()))
+            }
+        }
+    }
+}
+
+// `StoredValue` with the text resolved, `ValueSet` needs references to something implementing `Value`
+enum BorrowedValue<'a> {
+    F64(f64),
+    I64(i64),
+    U64(u64),
+    I128(i128),
+    U128(u128),
+    Bool(bool),
+    Str(&'a str),
+    Bytes(&'a [u8]),
+    Error(&'a (dyn Error + Send + Sync + 'static)),
+    // re-emitted verbatim through `record_debug`
+    Debug(DisplayValue<&'a str>),
+    // re-emitted through `record_value`
+    #[cfg(all(tracing_unstable, feature = "valuable"))]
+    Structured(valuable::Value<'a>),
+}
+
+impl BorrowedValue<'_> {
+    fn as_value(&self) -> &dyn Value {
+        match self {
+            BorrowedValue::F64(v) => v,
+            BorrowedValue::I64(v) => v,
+            BorrowedValue::U64(v) => v,
+            BorrowedValue::I128(v) => v,
+            BorrowedValue::U128(v) => v,
+            BorrowedValue::Bool(v) => v,
+            BorrowedValue::Str(v) => v,
+            BorrowedValue::Bytes(v) => v,
+            BorrowedValue::Error(v) => v,
+            BorrowedValue::Debug(v) => v,
+            #[cfg(all(tracing_unstable, feature = "valuable"))]
+            BorrowedValue::Structured(v) => v,
+        }
+    }
+}
+
+/// Borrowed value of a recorded field, with the same type it has been recorded with
+#[derive(Clone, Copy, Debug)]
+pub enum FieldValue<'a> {
+    F64(f64),
+    I64(i64),
+    U64(u64),
+    I128(i128),
+    U128(u128),
+    Bool(bool),
+    Str(&'a str),
+    Bytes(&'a [u8]),
+    Error(&'a (dyn Error + 'static)),
+    /// value recorded through `Debug`, already formatted; `%` values end up here too,
+    /// formatted with `Display`, since tracing records them through `record_debug`
+    Debug(&'a str),
+    /// value recorded through `record_value`, with the `valuable` feature and `--cfg tracing_unstable`
+    #[cfg(all(tracing_unstable, feature = "valuable"))]
+    Structured(valuable::Value<'a>),
+}
+
+impl<'a> FieldValue<'a> {
+    fn new(value: &'a StoredValue, text: &'a str) -> Self {
+        match value {
+            StoredValue::F64(v) => FieldValue::F64(*v),
+            StoredValue::I64(v) => FieldValue::I64(*v),
+            StoredValue::U64(v) => FieldValue::U64(*v),
+            StoredValue::I128(v) => FieldValue::I128(*v),
+            StoredValue::U128(v) => FieldValue::U128(*v),
+            StoredValue::Bool(v) => FieldValue::Bool(*v),
+            StoredValue::Str(range) => FieldValue::Str(&text[range.clone()]),
+            StoredValue::Bytes(v) => FieldValue::Bytes(v),
+            StoredValue::Error(v) => FieldValue::Error(v.as_ref()),
+            StoredValue::Debug(range) => FieldValue::Debug(&text[range.clone()]),
+            #[cfg(all(tracing_unstable, feature = "valuable"))]
+            StoredValue::Structured(v) => {
+                FieldValue::Structured(valuable::Valuable::as_value(v.as_ref()))
+            }
+        }
+    }
+
+    /// Returns the text of values recorded as strings or through `Debug`
+    pub fn as_str(&self) -> Option<&'a str> {
+        match self {
+            FieldValue::Str(v) | FieldValue::Debug(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Returns integer values that fit an `i64`, whatever type they have been recorded with
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            FieldValue::I64(v) => Some(v),
+            FieldValue::U64(v) => v.try_into().ok(),
+            FieldValue::I128(v) => v.try_into().ok(),
+            FieldValue::U128(v) => v.try_into().ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns integer values that fit an `u64`, whatever type they have been recorded with
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            FieldValue::I64(v) => v.try_into().ok(),
+            FieldValue::U64(v) => Some(v),
+            FieldValue::I128(v) => v.try_into().ok(),
+            FieldValue::U128(v) => v.try_into().ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Values recorded from an event, given to checks created with
+/// [`EventFormatter::with_fields`](crate::EventFormatter::with_fields)
+pub struct FieldSnapshot<'a> {
+    values: &'a [(Field, Option<StoredValue>)],
+    text: &'a str,
+}
+
+impl<'a> FieldSnapshot<'a> {
+    /// Returns the value of the field named `name`, if it has been recorded
+    pub fn get(&self, name: &str) -> Option<FieldValue<'a>> {
+        self.values
+            .iter()
+            .find(|(field, _)| field.name() == name)
+            .and_then(|(_, value)| value.as_ref())
+            .map(|value| FieldValue::new(value, self.text))
+    }
+
+    /// Returns the text of the field named `name`, if it has been recorded as a string or through `Debug`,
+    /// e.g. `snapshot.value_for("http.method") == Some("POST")`
+    pub fn value_for(&self, name: &str) -> Option<&'a str> {
+        self.get(name)?.as_str()
+    }
+
+    /// Iterates over the recorded fields, in recording order
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, FieldValue<'a>)> + 'a {
+        let text = self.text;
+        self.values.iter().filter_map(move |(field, value)| {
+            Some((field.name(), FieldValue::new(value.as_ref()?, text)))
+        })
+    }
+
+    /// Calls `f` with every recorded field and its value, in recording order, without allocating.
+    ///
+    /// Unlike [`iter`](Self::iter), values are handed over as the formatter receives them on
+    /// rewritten events: `value.record(field, visitor)` calls the same `Visit` method the
+    /// original event called, e.g. `record_debug` for `?` and `%` values.
+    pub fn for_each(&self, mut f: impl FnMut(&Field, &dyn Value)) {
+        for (field, value) in self.values {
+            if let Some(value) = value {
+                f(field, value.borrow(self.text).as_value());
+            }
+        }
+    }
+}
+
+// every visitor stores the values the same way, through its own `push` method
+// that gives access to the text buffer only if the value is going to be stored;
+// `message` needs no special case: format strings reach `record_debug` as `fmt::Arguments`
+// and are re-emitted through `record_debug` with the same text, literals stay `&str`
+macro_rules! record_methods {
+    () => {
+        fn record_f64(&mut self, field: &Field, value: f64) {
+            self.push(field, |_, _| StoredValue::F64(value));
+        }
+
+        fn record_i64(&mut self, field: &Field, value: i64) {
+            self.push(field, |_, _| StoredValue::I64(value));
+        }
+
+        fn record_u64(&mut self, field: &Field, value: u64) {
+            self.push(field, |_, _| StoredValue::U64(value));
+        }
+
+        fn record_i128(&mut self, field: &Field, value: i128) {
+            self.push(field, |_, _| StoredValue::I128(value));
+        }
+
+        fn record_u128(&mut self, field: &Field, value: u128) {
+            self.push(field, |_, _| StoredValue::U128(value));
+        }
+
+        fn record_bool(&mut self, field: &Field, value: bool) {
+            self.push(field, |_, _| StoredValue::Bool(value));
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.push(field, |text, max_len| {
+                StoredValue::Str(write_text_within(text, format_args!("{value}"), max_len))
+            });
+        }
+
+        fn record_bytes(&mut self, field: &Field, value: &[u8]) {
+            self.push(field, |_, _| StoredValue::Bytes(value.into()));
+        }
+
+        fn record_error(&mut self, field: &Field, value: &(dyn Error + 'static)) {
+            self.push(field, |_, _| {
+                StoredValue::Error(Box::new(CapturedError::new(value)))
+            });
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            self.push(field, |text, max_len| {
+                StoredValue::Debug(write_text_within(text, format_args!("{value:?}"), max_len))
+            });
+        }
+
+        #[cfg(all(tracing_unstable, feature = "valuable"))]
+        fn record_value(&mut self, field: &Field, value: valuable::Value<'_>) {
+            self.push(field, |_, _| {
+                StoredValue::Structured(Box::new(crate::structured::Owned::new(value)))
+            });
+        }
+    };
+}
+
+pub struct Visitor<const N: usize, B = PooledText> {
+    index: usize,
+    capacity: usize,
+    dropped: usize,
+    // when set, fields not named here are skipped
+    only: Option<&'static [&'static str]>,
+    // when set, string and `Debug` values are cut past this many bytes
+    max_len: Option<usize>,
+    values: [(Field, Option<StoredValue>); N],
+    text: B,
+}
+
+impl<const N: usize, B: TextBuffer> Visitor<N, B> {
+    /// `fields` is the number of fields declared by the event, when it doesn't fit
+    /// the last slot is kept free to report how many fields have been dropped
+    pub fn new(fields: usize) -> Self {
+        Visitor {
+            index: 0,
+            capacity: if fields > N { N.saturating_sub(1) } else { N },
+            dropped: 0,
+            only: None,
+            max_len: None,
+            values: [(); N].map(|_| (placeholder(), None)),
+            text: Default::default(),
+        }
+    }
+
+    /// Records only the fields named in `names`, the others aren't even counted as dropped
+    pub fn only(names: &'static [&'static str]) -> Self {
+        Visitor {
+            only: Some(names),
+            ..Visitor::new(names.len())
+        }
+    }
+
+    /// Cuts string and `Debug` values recorded from now on to `max_len` bytes, see
+    /// [`EventFormatterBuilder::max_field_len`](crate::EventFormatterBuilder::max_field_len)
+    pub fn max_len(self, max_len: Option<usize>) -> Self {
+        Visitor { max_len, ..self }
+    }
+
+    /// Replaces the text of string and `Debug` values for which `transform` returns `Some`
+    pub fn transform(&mut self, transform: &Transform) {
+        for (field, value) in &mut self.values[..self.index] {
+            if let Some(StoredValue::Str(range) | StoredValue::Debug(range)) = value {
+                if let Some(replacement) = transform(field, &self.text.as_str()[range.clone()]) {
+                    // the original text stays in the buffer, unreferenced
+                    *range = write_text(&mut self.text, format_args!("{replacement}"));
+                }
+            }
+        }
+    }
+
+    /// Hands the `ValueSet` made of the recorded values to `f`
+    pub fn with_value_set<R>(&self, fields: &FieldSet, f: impl FnOnce(&ValueSet<'_>) -> R) -> R {
+        let text = self.text.as_str();
+        let recorded = self.values[..self.index]
+            .iter()
+            .filter_map(|(field, value)| Some((field, value.as_ref()?.borrow(text))));
+        // the visitor never holds more than `N` values
+        with_value_set::<N, R>(self.index, recorded, fields, f)
+    }
+
+    pub fn snapshot(&self) -> FieldSnapshot<'_> {
+        FieldSnapshot {
+            values: &self.values[..self.index],
+            text: self.text.as_str(),
+        }
+    }
+
+    /// number of recorded fields, injected ones included
+    pub fn recorded(&self) -> usize {
+        self.index
+    }
+
+    /// number of recorded fields that didn't fit the visitor
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Frees the slots for `extra` injected fields, plus the one reporting the dropped fields,
+    /// evicting the last recorded values if needed
+    pub fn reserve(&mut self, extra: usize) {
+        while self.index > 0 && N - self.index < extra + usize::from(self.dropped > 0) {
+            self.index -= 1;
+            self.values[self.index] = (placeholder(), None);
+            self.dropped += 1;
+        }
+    }
+
+    pub fn record_str(&mut self, field: Field, value: &str) {
+        if self.index < N {
+            let range = write_text(&mut self.text, format_args!("{value}"));
+            self.values[self.index] = (field, Some(StoredValue::Str(range)));
+            self.index += 1;
+        }
+    }
+
+    /// stores the number of dropped fields into the reserved slot
+    pub fn record_dropped(&mut self, field: Field) {
+        if self.index < N {
+            self.values[self.index] = (field, Some(StoredValue::U64(self.dropped as u64)));
+            self.index += 1;
+        }
+    }
+
+    fn push(&mut self, field: &Field, value: impl FnOnce(&mut B, Option<usize>) -> StoredValue) {
+        if self.only.is_some_and(|only| !only.contains(&field.name())) {
+            return;
+        }
+
+        // some instrumentation records a field more than once, the last value wins
+        let recorded = &mut self.values[..self.index];
+        if let Some((_, slot)) = recorded.iter_mut().find(|(recorded, _)| recorded == field) {
+            *slot = Some(value(&mut self.text, self.max_len));
+            return;
+        }
+
+        if self.index >= self.capacity {
+            self.dropped += 1;
+            return;
+        }
+
+        self.values[self.index] = (
+            clone_field(field),
+            Some(value(&mut self.text, self.max_len)),
+        );
+        self.index += 1;
+    }
+}
+
+impl<const N: usize, B: TextBuffer> Visit for Visitor<N, B> {
+    record_methods!();
+}
+
+/// Records every field of `event` into a visitor of `N` slots, string and `Debug` values
+/// cut to `max_len` bytes
+pub fn record<const N: usize, B: TextBuffer>(
+    event: &Event<'_>,
+    max_len: Option<usize>,
+) -> Visitor<N, B> {
+    let mut visitor = Visitor::new(event.fields().count()).max_len(max_len);
+    event.record(&mut visitor);
+    visitor
+}
+
+// `ValueSet` can only be built out of arrays, so pick the smallest one that holds the `len` recorded values,
+// `MAX` is the size used past the biggest tier
+fn with_value_set<'a, const MAX: usize, R>(
+    len: usize,
+    recorded: impl Iterator<Item = (&'a Field, BorrowedValue<'a>)>,
+    fields: &FieldSet,
+    f: impl FnOnce(&ValueSet<'_>) -> R,
+) -> R {
+    match len {
+        0..=4 => sized::<4, R>(recorded, fields, f),
+        5..=16 => sized::<16, R>(recorded, fields, f),
+        17..=64 => sized::<64, R>(recorded, fields, f),
+        65..=256 => sized::<256, R>(recorded, fields, f),
+        _ => sized::<MAX, R>(recorded, fields, f),
+    }
+}
+
+fn sized<'a, const N: usize, R>(
+    mut recorded: impl Iterator<Item = (&'a Field, BorrowedValue<'a>)>,
+    fields: &FieldSet,
+    f: impl FnOnce(&ValueSet<'_>) -> R,
+) -> R {
+    let borrowed: [_; N] = std::array::from_fn(|_| recorded.next());
+
+    // the slots past the recorded values hold placeholders that `ValueSet` ignores,
+    // since they belong to a different callsite
+    let placeholder = placeholder();
+    let values: [_; N] = std::array::from_fn(|i| match &borrowed[i] {
+        Some((field, value)) => (*field, Some(value.as_value())),
+        None => (&placeholder, None),
+    });
+    f(&fields.value_set(&values))
+}
+
+/// Visitor without a fixed capacity, it grows as fields are recorded
+// values are kept inline up to `INLINE` fields, spilling to the heap only for bigger events
+pub struct DynVisitor<const INLINE: usize> {
+    values: SmallVec<[(Field, StoredValue); INLINE]>,
+    text: PooledText,
+}
+
+impl<const INLINE: usize> DynVisitor<INLINE> {
+    /// `fields` is the number of fields declared by the event
+    pub fn new(fields: usize) -> Self {
+        DynVisitor {
+            values: SmallVec::with_capacity(fields),
+            text: Default::default(),
+        }
+    }
+
+    /// number of recorded fields, injected ones included
+    pub fn recorded(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Hands the `ValueSet` made of the recorded values to `f`,
+    /// values exceeding 1024 are dropped.
+    pub fn with_value_set<R>(&self, fields: &FieldSet, f: impl FnOnce(&ValueSet<'_>) -> R) -> R {
+        let text = self.text.as_str();
+        let recorded = self
+            .values
+            .iter()
+            .map(|(field, value)| (field, value.borrow(text)));
+        with_value_set::<1024, R>(self.values.len(), recorded, fields, f)
+    }
+
+    pub fn record_str(&mut self, field: Field, value: &str) {
+        let range = write_text(&mut self.text, format_args!("{value}"));
+        self.values.push((field, StoredValue::Str(range)));
+    }
+
+    fn push(
+        &mut self,
+        field: &Field,
+        value: impl FnOnce(&mut PooledText, Option<usize>) -> StoredValue,
+    ) {
+        // values are never cut, `DynEventFormatter` doesn't have a length limit
+        let value = value(&mut self.text, None);
+        // like `Visitor`, a field recorded twice keeps only its last value
+        match self
+            .values
+            .iter_mut()
+            .find(|(recorded, _)| recorded == field)
+        {
+            Some((_, slot)) => *slot = value,
+            None => self.values.push((clone_field(field), value)),
+        }
+    }
+}
+
+impl<const INLINE: usize> Visit for DynVisitor<INLINE> {
+    record_methods!();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        error::Error,
+        fmt::{self, Debug},
+        sync::{Arc, Mutex},
+    };
+
+    use tracing::{
+        field::{Field, Visit},
+        Event, Subscriber,
+    };
+    use tracing_subscriber::{layer::Context, prelude::*, registry::Registry, Layer};
+
+    use super::{clone_field, placeholder, DynVisitor, Visitor, FAKE_CALLSITE, FAKE_FIELD_NAME};
+    use crate::{tests::TEST_META, Rewrite, RewriteLayer};
+
+    // records every field of every event as `name: type = value`
+    #[derive(Clone, Default)]
+    struct Typed(Arc<Mutex<Vec<String>>>);
+
+    impl Typed {
+        fn push(&mut self, field: &Field, kind: &str, value: impl Debug) {
+            let line = format!("{}: {kind} = {value:?}", field.name());
+            self.0.lock().unwrap().push(line);
+        }
+    }
+
+    impl Visit for Typed {
+        fn record_f64(&mut self, field: &Field, value: f64) {
+            self.push(field, "f64", value);
+        }
+
+        fn record_i64(&mut self, field: &Field, value: i64) {
+            self.push(field, "i64", value);
+        }
+
+        fn record_u64(&mut self, field: &Field, value: u64) {
+            self.push(field, "u64", value);
+        }
+
+        fn record_i128(&mut self, field: &Field, value: i128) {
+            self.push(field, "i128", value);
+        }
+
+        fn record_u128(&mut self, field: &Field, value: u128) {
+            self.push(field, "u128", value);
+        }
+
+        fn record_bool(&mut self, field: &Field, value: bool) {
+            self.push(field, "bool", value);
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.push(field, "str", value);
+        }
+
+        fn record_bytes(&mut self, field: &Field, value: &[u8]) {
+            self.push(field, "bytes", value);
+        }
+
+        fn record_error(&mut self, field: &Field, value: &(dyn Error + 'static)) {
+            let source = value.source().map(ToString::to_string);
+            self.push(field, "error", (value.to_string(), source));
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            self.push(field, "debug", format!("{value:?}"));
+        }
+    }
+
+    impl<S: Subscriber> Layer<S> for Typed {
+        fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+            event.record(&mut self.clone());
+        }
+    }
+
+    #[derive(Debug)]
+    struct Failure(Option<Box<Failure>>);
+
+    impl fmt::Display for Failure {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let level = if self.0.is_some() { "outer" } else { "inner" };
+            f.write_str(level)
+        }
+    }
+
+    impl Error for Failure {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            self.0
+                .as_deref()
+                .map(|source| source as &(dyn Error + 'static))
+        }
+    }
+
+    #[test]
+    fn every_type_is_kept() {
+        let capture = |rewrite: bool| {
+            let typed = Typed::default();
+            let subscriber = Registry::default()
+                .with(RewriteLayer::<16, _, _>::new(typed.clone(), move |_| {
+                    rewrite.then(Rewrite::default)
+                }));
+
+            tracing::subscriber::with_default(subscriber, || {
+                let error = Failure(Some(Box::new(Failure(None))));
+                tracing::info!(
+                    float = 0.5,
+                    signed = -1,
+                    unsigned = 1u64,
+                    big_signed = i128::MIN,
+                    big_unsigned = u128::MAX,
+                    flag = true,
+                    text = "text",
+                    bytes = &b"\x00\xff"[..],
+                    error = &error as &(dyn Error + 'static),
+                    debug = ?[1, 2],
+                    display = %1.5,
+                    "message"
+                );
+            });
+
+            let lines = typed.0.lock().unwrap().clone();
+            lines
+        };
+
+        let original = capture(false);
+        assert_eq!(
+            original,
+            [
+                "message: debug = \"message\"",
+                "float: f64 = 0.5",
+                "signed: i64 = -1",
+                "unsigned: u64 = 1",
+                "big_signed: i128 = -170141183460469231731687303715884105728",
+                "big_unsigned: u128 = 340282366920938463463374607431768211455",
+                "flag: bool = true",
+                "text: str = \"text\"",
+                "bytes: bytes = [0, 255]",
+                "error: error = (\"outer\", Some(\"inner\"))",
+                "debug: debug = \"[1, 2]\"",
+                "display: debug = \"1.5\"",
+            ]
+        );
+        assert_eq!(capture(true), original);
+    }
+
+    #[test]
+    fn snapshot_for_each() {
+        let fields = TEST_META.fields();
+        let mut visitor = Visitor::<10>::new(fields.len());
+        visitor.record_debug(&fields.field("first").unwrap(), &[1, 2]);
+        visitor.record_u64(&fields.field("second").unwrap(), 1);
+
+        let mut typed = Typed::default();
+        let mut names = Vec::new();
+        visitor.snapshot().for_each(|field, value| {
+            names.push(field.name());
+            value.record(field, &mut typed);
+        });
+        assert_eq!(names, ["first", "second"]);
+        assert_eq!(
+            *typed.0.lock().unwrap(),
+            ["first: debug = \"[1, 2]\"", "second: u64 = 1"]
+        );
+    }
+
+    #[test]
+    fn dyn_visitor_keeps_types() {
+        let fields = TEST_META.fields();
+        let mut visitor = DynVisitor::<8>::new(fields.len());
+        visitor.record_bool(&fields.field("first").unwrap(), true);
+        visitor.record_u64(&fields.field("second").unwrap(), 1);
+
+        let mut typed = Typed::default();
+        visitor.with_value_set(fields, |valueset| valueset.record(&mut typed));
+        assert_eq!(
+            *typed.0.lock().unwrap(),
+            ["first: bool = true", "second: u64 = 1"]
+        );
+    }
+
+    #[test]
+    fn value_set_holds_only_recorded_fields() {
+        let fields = TEST_META.fields();
+        let mut visitor = Visitor::<10>::new(fields.len());
+        visitor.record_i64(&fields.field("first").unwrap(), 1);
+
+        struct Names(Vec<&'static str>);
+
+        impl Visit for Names {
+            fn record_debug(&mut self, field: &Field, _: &dyn Debug) {
+                self.0.push(field.name());
+            }
+        }
+
+        visitor.with_value_set(fields, |valueset| {
+            assert_eq!(valueset.len(), 1);
+            let mut names = Names(Vec::new());
+            valueset.record(&mut names);
+            assert_eq!(names.0, ["first"]);
+        });
+    }
+
+    #[test]
+    fn fields_recorded_twice_keep_the_last_value() {
+        struct Values(Vec<String>);
+
+        impl Visit for Values {
+            fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+                self.0.push(format!("{}={value:?}", field.name()));
+            }
+        }
+
+        let fields = TEST_META.fields();
+        let first = fields.field("first").unwrap();
+        let second = fields.field("second").unwrap();
+        let record = |visitor: &mut dyn Visit| {
+            visitor.record_i64(&first, 1);
+            visitor.record_i64(&second, 2);
+            visitor.record_i64(&first, 3);
+        };
+        let values = |valueset: &tracing::field::ValueSet<'_>| {
+            let mut values = Values(Vec::new());
+            valueset.record(&mut values);
+            values.0
+        };
+
+        let mut visitor = Visitor::<10>::new(fields.len());
+        record(&mut visitor);
+        visitor.with_value_set(fields, |valueset| {
+            assert_eq!(valueset.len(), 2);
+            assert_eq!(values(valueset), ["first=3", "second=2"]);
+        });
+
+        let mut visitor = DynVisitor::<8>::new(fields.len());
+        record(&mut visitor);
+        visitor.with_value_set(fields, |valueset| {
+            assert_eq!(values(valueset), ["first=3", "second=2"]);
+        });
+    }
+
+    #[test]
+    fn fields_named_like_the_placeholder() {
+        let formatter = crate::EventFormatter::builder()
+            .formatter(tracing_subscriber::fmt::format().compact().without_time())
+            .check_with_fields(|_, fields| {
+                assert_eq!(fields.get(FAKE_FIELD_NAME).unwrap().as_i64(), Some(1));
+                Some(tracing::Level::WARN)
+            })
+            .build();
+
+        // every slot past the recorded fields holds a placeholder
+        let output = crate::tests::capture_with(
+            tracing_subscriber::fmt::format::DefaultFields::new(),
+            formatter,
+            || tracing::error!(foo = 1, bar = 2, "test"),
+        );
+
+        assert_eq!(
+            output,
+            " WARN tracing_rewrite::visitor::tests: test foo=1 bar=2\n"
+        );
+    }
+
+    #[test]
+    fn cloned_field_is_equal() {
+        let field = placeholder();
+        let cloned = clone_field(&field);
+        assert_eq!(cloned, field);
+        assert_eq!(cloned.name(), FAKE_FIELD_NAME);
+        assert_eq!(cloned.index(), field.index());
+        assert_eq!(
+            cloned.callsite(),
+            tracing::callsite::Identifier(&FAKE_CALLSITE)
+        );
+    }
+}
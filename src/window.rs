@@ -0,0 +1,83 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use tracing::Metadata;
+
+use crate::Decision;
+
+/// Applies `check` only until `window` has elapsed since this call, events are left untouched
+/// afterwards, e.g. to promote the `DEBUG` events of a dependency during startup.
+///
+/// The window starts when the check is built, usually with the subscriber at process start.
+/// Once it has elapsed the clock isn't read anymore.
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use tracing::Level;
+/// use tracing_rewrite::{time_windowed, EventFormatter};
+/// use tracing_subscriber::fmt;
+///
+/// let formatter = EventFormatter::<10, _, _>::new(
+///     fmt::format(),
+///     time_windowed(
+///         |metadata| (*metadata.level() == Level::DEBUG).then_some(Level::INFO),
+///         Duration::from_secs(60),
+///     ),
+/// );
+/// ```
+pub fn time_windowed<T, R>(
+    check: T,
+    window: Duration,
+) -> impl Fn(&Metadata<'static>) -> Decision + Send + Sync
+where
+    T: Fn(&Metadata<'static>) -> R + Send + Sync,
+    R: Into<Decision>,
+{
+    let start = Instant::now();
+    let elapsed = AtomicBool::new(false);
+    move |metadata| {
+        if elapsed.load(Ordering::Relaxed) {
+            return Decision::Keep;
+        }
+        if start.elapsed() >= window {
+            elapsed.store(true, Ordering::Relaxed);
+            return Decision::Keep;
+        }
+        check(metadata).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tracing::Level;
+    use tracing_subscriber::fmt::{self, format::DefaultFields};
+
+    use crate::{tests::capture_with, EventFormatter};
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn window() {
+        let formatter = EventFormatter::<10, _, _>::time_windowed(
+            fmt::format().compact().without_time(),
+            |_| Some(Level::ERROR),
+            Duration::from_millis(100),
+        );
+
+        let output = capture_with(DefaultFields::new(), formatter, || {
+            tracing::warn!("startup");
+            std::thread::sleep(Duration::from_millis(150));
+            tracing::warn!("later");
+        });
+
+        assert_eq!(
+            output,
+            "ERROR tracing_rewrite::window::tests: startup\n WARN tracing_rewrite::window::tests: \
+             later\n"
+        );
+    }
+}
@@ -0,0 +1,188 @@
+//! End-to-end tests wrapping the JSON formatter, the most sensitive to field types
+
+use std::{
+    io,
+    sync::{Arc, Mutex},
+};
+
+use serde_json::Value;
+use tracing::{Level, Metadata};
+use tracing_rewrite::{Decision, EventFormatter, Rewrite, TRUNCATED_FIELD_NAME};
+use tracing_subscriber::fmt::{self, format::JsonFields, MakeWriter};
+
+#[derive(Clone, Default)]
+struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for Buffer {
+    type Writer = Self;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+// formats the events emitted by `f` as JSON lines, rewritten by `check`
+fn capture<const VISITOR_SIZE: usize, R>(
+    check: impl Fn(&Metadata<'static>) -> Option<R> + Send + Sync + 'static,
+    f: impl FnOnce(),
+) -> Vec<Value>
+where
+    R: Into<Rewrite> + 'static,
+{
+    let buffer = Buffer::default();
+    let subscriber = fmt::Subscriber::builder()
+        .with_max_level(Level::TRACE)
+        .with_ansi(false)
+        .with_writer(buffer.clone())
+        .fmt_fields(JsonFields::new())
+        .event_format(EventFormatter::<VISITOR_SIZE, _, _>::new(
+            fmt::format().json().without_time().with_span_list(true),
+            check,
+        ))
+        .finish();
+    tracing::subscriber::with_default(subscriber, f);
+
+    let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+    output
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+fn log() {
+    let span = tracing::info_span!("request", id = 7, path = "/health");
+    let _guard = span.enter();
+    tracing::error!(
+        signed = -1,
+        unsigned = 2u64,
+        float = 0.5,
+        flag = true,
+        text = "plain",
+        list = ?[1, 2],
+        "test {}",
+        42
+    );
+}
+
+#[test]
+fn only_the_level_changes() {
+    let mut original = capture::<10, Level>(|_| None, log);
+    let mut rewritten = capture::<10, _>(|_| Some(Level::WARN), log);
+
+    assert_eq!(original[0]["level"], "ERROR");
+    assert_eq!(rewritten[0]["level"], "WARN");
+
+    original[0].as_object_mut().unwrap().remove("level");
+    rewritten[0].as_object_mut().unwrap().remove("level");
+    assert_eq!(rewritten, original);
+}
+
+#[test]
+fn fields_keep_their_type() {
+    let rewritten = capture::<10, _>(|_| Some(Level::WARN), log);
+    let fields = &rewritten[0]["fields"];
+
+    assert_eq!(fields["message"], "test 42");
+    assert_eq!(fields["signed"], -1);
+    assert_eq!(fields["unsigned"], 2);
+    assert_eq!(fields["float"], 0.5);
+    assert_eq!(fields["flag"], true);
+    assert_eq!(fields["text"], "plain");
+    assert_eq!(fields["list"], "[1, 2]");
+}
+
+#[test]
+fn level_only_rewrites_keep_numbers() {
+    let emit = || {
+        tracing::error!(
+            min = i64::MIN,
+            max = u64::MAX,
+            float = -1.5e300,
+            flag = false,
+            "test"
+        )
+    };
+    let buffer = Buffer::default();
+    let subscriber = fmt::Subscriber::builder()
+        .with_writer(buffer.clone())
+        .fmt_fields(JsonFields::new())
+        .event_format(EventFormatter::<10, _, _>::new(
+            fmt::format().json().without_time(),
+            |_| Decision::SetLevel(Level::WARN),
+        ))
+        .finish();
+    tracing::subscriber::with_default(subscriber, emit);
+
+    let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+    let rewritten: Value = serde_json::from_str(&output).unwrap();
+    let fields = &rewritten["fields"];
+    assert_eq!(rewritten["level"], "WARN");
+    assert_eq!(fields["min"].as_i64(), Some(i64::MIN));
+    assert_eq!(fields["max"].as_u64(), Some(u64::MAX));
+    assert_eq!(fields["float"].as_f64(), Some(-1.5e300));
+    assert_eq!(fields["flag"].as_bool(), Some(false));
+}
+
+#[test]
+fn spans_are_kept() {
+    let rewritten = capture::<10, _>(|_| Some(Level::WARN), log);
+
+    assert_eq!(rewritten[0]["span"]["name"], "request");
+    assert_eq!(rewritten[0]["span"]["id"], 7);
+    assert_eq!(rewritten[0]["spans"][0]["path"], "/health");
+}
+
+#[test]
+fn truncated_fields_are_counted() {
+    let rewritten = capture::<4, _>(|_| Some(Level::WARN), log);
+    let fields = rewritten[0]["fields"].as_object().unwrap();
+
+    assert_eq!(fields.len(), 4);
+    assert_eq!(fields[TRUNCATED_FIELD_NAME], 4);
+}
+
+#[derive(Debug)]
+struct Failure(&'static str, Option<Box<Failure>>);
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl std::error::Error for Failure {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.1.as_deref().map(|e| e as _)
+    }
+}
+
+#[test]
+fn errors_render_like_the_original() {
+    let emit = || {
+        let error = Failure("outer", Some(Box::new(Failure("inner", None))));
+        tracing::warn!(
+            error = &error as &(dyn std::error::Error + 'static),
+            "failed"
+        );
+    };
+    let mut original = capture::<10, Level>(|_| None, emit);
+    let mut rewritten = capture::<10, _>(|_| Some(Level::ERROR), emit);
+
+    // the error is handed back through `record_error`, the JSON formatter of `tracing-subscriber`
+    // formats it with `Display`, without the `error.sources` of the text formatters
+    assert_eq!(rewritten[0]["level"], "ERROR");
+    assert_eq!(rewritten[0]["fields"]["error"], "outer");
+    original[0].as_object_mut().unwrap().remove("level");
+    rewritten[0].as_object_mut().unwrap().remove("level");
+    assert_eq!(rewritten, original);
+}
@@ -0,0 +1,79 @@
+//! Rewritten events written through a writer handing the bytes to another thread,
+//! the way `tracing_appender::non_blocking` does
+
+use std::{
+    io,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+
+use tracing::Level;
+use tracing_rewrite::DefaultEventFormatter;
+use tracing_subscriber::fmt::{self, MakeWriter};
+
+// every write is copied and sent to the worker, which writes it after the event has been handled
+#[derive(Clone)]
+struct NonBlocking(Sender<Vec<u8>>);
+
+impl io::Write for NonBlocking {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .send(buf.to_vec())
+            .map_err(|e| io::Error::new(io::ErrorKind::BrokenPipe, e))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for NonBlocking {
+    type Writer = Self;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+fn worker(lines: Receiver<Vec<u8>>) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        for line in lines {
+            output.extend(line);
+        }
+        String::from_utf8(output).unwrap()
+    })
+}
+
+#[test]
+fn non_blocking_writer() {
+    let (sender, receiver) = mpsc::channel();
+    let worker = worker(receiver);
+
+    let subscriber = fmt::Subscriber::builder()
+        .with_ansi(false)
+        .with_writer(NonBlocking(sender))
+        .event_format(DefaultEventFormatter::new(
+            fmt::format().compact().without_time(),
+            |metadata| (*metadata.level() == Level::ERROR).then_some(Level::WARN),
+        ))
+        .finish();
+    tracing::subscriber::with_default(subscriber, || {
+        for i in 0..100 {
+            tracing::error!(i, "rewritten");
+            tracing::info!(i, "untouched");
+        }
+    });
+
+    // dropping the subscriber closes the channel
+    let output = worker.join().unwrap();
+    let lines = output.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 200);
+    for (i, pair) in lines.chunks(2).enumerate() {
+        // with `trace_rewrites`, rewritten events end with a ` from=.. to=..` annotation
+        let rewritten = pair[0].split(" from=").next().unwrap();
+        assert_eq!(rewritten, format!(" WARN non_blocking: rewritten i={i}"));
+        assert_eq!(pair[1], format!(" INFO non_blocking: untouched i={i}"));
+    }
+}